@@ -0,0 +1,111 @@
+use clap::{Args, Parser, Subcommand};
+use uuid::Uuid;
+
+// reads the same .env, CONFIG_PATH and CHAT__* overrides as the server
+#[derive(Parser)]
+#[command(
+    name = "chatctl",
+    about = "Operate the chat server's database and redis"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(subcommand, about = "List, create and delete rooms")]
+    Rooms(RoomsCommand),
+    #[command(subcommand, about = "Remove stored messages")]
+    Messages(MessagesCommand),
+    #[command(subcommand, about = "Inspect and reset rate limit counters")]
+    RateLimit(RateLimitCommand),
+    #[command(subcommand, about = "Ban users or ip addresses")]
+    Bans(BansCommand),
+    #[command(subcommand, about = "Apply or check database migrations")]
+    Migrate(MigrateCommand),
+}
+
+#[derive(Subcommand)]
+pub enum RoomsCommand {
+    List,
+    Create,
+    Delete { uuid: Uuid },
+}
+
+#[derive(Subcommand)]
+pub enum MessagesCommand {
+    #[command(about = "Delete every message of a room")]
+    Purge { room_uuid: Uuid },
+}
+
+#[derive(Subcommand)]
+pub enum RateLimitCommand {
+    List {
+        #[arg(
+            default_value = "*",
+            help = "glob matched against the subject, e.g. chat:* or *:ip:10.0.*"
+        )]
+        pattern: String,
+    },
+    Reset {
+        #[arg(help = "subject of the counter, e.g. create_room:ip:10.0.0.1")]
+        subject: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum BansCommand {
+    List,
+    Add {
+        #[command(flatten)]
+        target: BanTarget,
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    Remove {
+        #[command(flatten)]
+        target: BanTarget,
+    },
+}
+
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+pub struct BanTarget {
+    #[arg(long)]
+    pub user: Option<String>,
+    #[arg(long)]
+    pub ip: Option<String>,
+}
+
+#[derive(Subcommand)]
+pub enum MigrateCommand {
+    Run,
+    Pending,
+    Check,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BansCommand, Cli, Command};
+    use clap::{CommandFactory, Parser};
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+    #[test]
+    fn test_ban_target_is_exclusive() {
+        let cli = Cli::try_parse_from(["chatctl", "bans", "add", "--user", "anonymous_1"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Bans(BansCommand::Add { target, .. }) if target.user.as_deref() == Some("anonymous_1")
+        ));
+
+        assert!(Cli::try_parse_from(["chatctl", "bans", "add"]).is_err());
+        assert!(
+            Cli::try_parse_from(["chatctl", "bans", "add", "--user", "a", "--ip", "10.0.0.1"])
+                .is_err()
+        );
+    }
+}
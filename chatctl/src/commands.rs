@@ -0,0 +1,247 @@
+use std::{env, sync::Arc};
+
+use infra::db::{
+    audit::{AuditEntry, AuditLog},
+    migrations::{self, MigrateAction},
+    models::{Ban, BanKind, Message, Room},
+};
+use redis::{AsyncCommands, AsyncTypedCommands, Client};
+use shared::types::DefaultError;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::cli::{
+    BanTarget, BansCommand, Command, MessagesCommand, MigrateCommand, RateLimitCommand,
+    RoomsCommand,
+};
+
+const RATE_LIMIT_PREFIX: &str = "rate_limiter:";
+
+pub async fn run(
+    command: Command,
+    db_pool: Arc<PgPool>,
+    redis_client: Client,
+) -> Result<(), DefaultError> {
+    match command {
+        Command::Rooms(command) => rooms(command, db_pool, redis_client).await,
+        Command::Messages(MessagesCommand::Purge { room_uuid }) => {
+            let room = find_room(&db_pool, room_uuid).await?;
+            let mut tx = db_pool.begin().await?;
+            let purged = Message::purge(&mut tx, room.get_id(), &actor()).await?;
+            tx.commit().await?;
+
+            println!("purged {} messages from room {}", purged, room_uuid);
+            Ok(())
+        }
+        Command::RateLimit(command) => rate_limit(command, db_pool, redis_client).await,
+        Command::Bans(command) => bans(command, db_pool).await,
+        Command::Migrate(command) => migrate(command, &db_pool).await,
+    }
+}
+
+async fn rooms(
+    command: RoomsCommand,
+    db_pool: Arc<PgPool>,
+    redis_client: Client,
+) -> Result<(), DefaultError> {
+    match command {
+        RoomsCommand::List => {
+            for room in Room::read(None, Some(Arc::clone(&db_pool)), None).await? {
+                println!("{}\t{}", room.get_uuid(), room.get_created_at());
+            }
+            // rooms created through the api only reach postgres once someone joins
+            let mut conn = redis_client.get_multiplexed_async_connection().await?;
+            for key in scan_keys(&mut conn, "room:*").await? {
+                println!("{}\tpending", key.trim_start_matches("room:"));
+            }
+        }
+        RoomsCommand::Create => {
+            let mut tx = db_pool.begin().await?;
+            let room = Room::create(&mut tx, None).await?;
+            tx.commit().await?;
+
+            println!("{}", room.get_uuid());
+        }
+        RoomsCommand::Delete { uuid } => {
+            let mut conn = redis_client.get_multiplexed_async_connection().await?;
+            let pending = AsyncTypedCommands::del(&mut conn, format!("room:{}", uuid)).await? > 0;
+
+            let rooms = Room::read(None, Some(Arc::clone(&db_pool)), Some(uuid)).await?;
+            if rooms.is_empty() && !pending {
+                return Err(format!("room {} not found", uuid).into());
+            }
+            let mut tx = db_pool.begin().await?;
+            for room in rooms {
+                Room::delete(&mut tx, room.get_id(), &actor()).await?;
+            }
+            tx.commit().await?;
+
+            println!("deleted room {}", uuid);
+        }
+    }
+
+    Ok(())
+}
+
+async fn rate_limit(
+    command: RateLimitCommand,
+    db_pool: Arc<PgPool>,
+    redis_client: Client,
+) -> Result<(), DefaultError> {
+    let mut conn = redis_client.get_multiplexed_async_connection().await?;
+
+    match command {
+        RateLimitCommand::List { pattern } => {
+            for key in scan_keys(&mut conn, &format!("{}{}", RATE_LIMIT_PREFIX, pattern)).await? {
+                let remaining = AsyncTypedCommands::get(&mut conn, &key).await?;
+                let ttl: i64 = AsyncCommands::ttl(&mut conn, &key).await?;
+                println!(
+                    "{}\tremaining={}\tttl={}s",
+                    key.trim_start_matches(RATE_LIMIT_PREFIX),
+                    remaining.unwrap_or_default(),
+                    ttl
+                );
+            }
+        }
+        RateLimitCommand::Reset { subject } => {
+            let key = format!("{}{}", RATE_LIMIT_PREFIX, subject);
+            if AsyncTypedCommands::del(&mut conn, &key).await? == 0 {
+                return Err(format!("no rate limit counter for {}", subject).into());
+            }
+            let mut tx = db_pool.begin().await?;
+            AuditLog::record(
+                &mut tx,
+                AuditEntry::new(&actor(), "rate_limit.reset").target(subject.clone()),
+            )
+            .await?;
+            tx.commit().await?;
+
+            println!("reset rate limit for {}", subject);
+        }
+    }
+
+    Ok(())
+}
+
+async fn bans(command: BansCommand, db_pool: Arc<PgPool>) -> Result<(), DefaultError> {
+    match command {
+        BansCommand::List => {
+            for ban in Ban::read(None, Some(Arc::clone(&db_pool))).await? {
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    ban.get_kind(),
+                    ban.get_value(),
+                    ban.get_created_at(),
+                    ban.get_reason().unwrap_or_default()
+                );
+            }
+        }
+        BansCommand::Add { target, reason } => {
+            let (kind, value) = ban_target(target);
+            let mut tx = db_pool.begin().await?;
+            Ban::create(&mut tx, kind, value.clone(), reason, &actor()).await?;
+            tx.commit().await?;
+
+            println!("banned {} {}", kind.as_str(), value);
+        }
+        BansCommand::Remove { target } => {
+            let (kind, value) = ban_target(target);
+            let mut tx = db_pool.begin().await?;
+            if !Ban::delete(&mut tx, kind, value.clone(), &actor()).await? {
+                return Err(format!("no {} ban on {}", kind.as_str(), value).into());
+            }
+            tx.commit().await?;
+
+            println!("unbanned {} {}", kind.as_str(), value);
+        }
+    }
+
+    Ok(())
+}
+
+async fn migrate(command: MigrateCommand, db_pool: &PgPool) -> Result<(), DefaultError> {
+    let action = match command {
+        MigrateCommand::Run => MigrateAction::Run,
+        MigrateCommand::Pending => MigrateAction::Pending,
+        MigrateCommand::Check => MigrateAction::Check,
+    };
+    for line in migrations::migrate(action, db_pool).await? {
+        println!("{line}");
+    }
+
+    Ok(())
+}
+
+async fn find_room(db_pool: &Arc<PgPool>, uuid: Uuid) -> Result<Room, DefaultError> {
+    Room::read(None, Some(Arc::clone(db_pool)), Some(uuid))
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("room {} not found", uuid).into())
+}
+
+// audit log actor, the os user running chatctl when known
+fn actor() -> String {
+    match env::var("USER") {
+        Ok(user) if !user.is_empty() => format!("chatctl:{}", user),
+        _ => "chatctl".to_string(),
+    }
+}
+
+fn ban_target(target: BanTarget) -> (BanKind, String) {
+    match (target.user, target.ip) {
+        (Some(user), _) => (BanKind::User, user),
+        (None, Some(ip)) => (BanKind::Ip, ip),
+        (None, None) => unreachable!("clap requires one ban target"),
+    }
+}
+
+async fn scan_keys(
+    conn: &mut redis::aio::MultiplexedConnection,
+    pattern: &str,
+) -> Result<Vec<String>, DefaultError> {
+    let mut keys = Vec::new();
+    let mut iter = AsyncCommands::scan_match::<_, String>(conn, pattern).await?;
+    while let Some(key) = iter.next_item().await {
+        keys.push(key);
+    }
+    keys.sort_unstable();
+
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run;
+    use crate::cli::{Command, MigrateCommand};
+    use dotenvy::dotenv;
+    use infra::{cache::get_redis_client, db::create_pool};
+    use shared::config::DatabaseConfig;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_migrate_command() {
+        dotenv().ok();
+        let config = DatabaseConfig {
+            min_connections: 0,
+            max_connections: 1,
+            ..DatabaseConfig::default()
+        };
+        let db_pool = Arc::new(create_pool(&config).await.unwrap());
+        let redis_client = get_redis_client().unwrap();
+
+        for command in [
+            MigrateCommand::Run,
+            MigrateCommand::Pending,
+            MigrateCommand::Check,
+        ] {
+            run(
+                Command::Migrate(command),
+                Arc::clone(&db_pool),
+                redis_client.clone(),
+            )
+            .await
+            .unwrap();
+        }
+    }
+}
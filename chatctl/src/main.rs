@@ -0,0 +1,37 @@
+use std::{process, sync::Arc};
+
+use clap::Parser;
+use dotenvy::dotenv;
+use infra::{cache::get_redis_client, db::create_pool, logging::init_logger};
+use shared::config::Config;
+
+use crate::cli::Cli;
+
+mod cli;
+mod commands;
+
+#[tokio::main]
+async fn main() {
+    dotenv().ok();
+    let cli = Cli::parse();
+
+    let config = Config::load().unwrap_or_else(|error| {
+        eprintln!("failed to load config: {error}");
+        process::exit(1)
+    });
+    init_logger(&config.log);
+
+    let db_pool = Arc::new(create_pool(&config.database).await.unwrap_or_else(|error| {
+        eprintln!("failed to create db pool: {error}");
+        process::exit(1)
+    }));
+    let redis_client = get_redis_client().unwrap_or_else(|error| {
+        eprintln!("failed to create redis client: {error}");
+        process::exit(1)
+    });
+
+    if let Err(error) = commands::run(cli.command, db_pool, redis_client).await {
+        eprintln!("{error}");
+        process::exit(1)
+    }
+}
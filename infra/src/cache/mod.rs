@@ -1,6 +1,6 @@
 use std::env;
 
-use redis::Client;
+use redis::{AsyncTypedCommands, Client};
 
 pub fn get_redis_client() -> redis::RedisResult<Client> {
     let redis_url = env::var("REDIS_URL").expect("REDIS_URL most be set in .env");
@@ -8,9 +8,15 @@ pub fn get_redis_client() -> redis::RedisResult<Client> {
     Ok(client)
 }
 
+pub async fn ping_redis(client: &Client) -> redis::RedisResult<()> {
+    let mut conn = client.get_multiplexed_async_connection().await?;
+    conn.ping().await?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
-    use super::get_redis_client;
+    use super::{get_redis_client, ping_redis};
     use dotenvy::dotenv;
 
     #[test]
@@ -18,4 +24,12 @@ mod tests {
         dotenv().ok();
         assert!(get_redis_client().is_ok())
     }
+    #[tokio::test]
+    async fn test_ping_redis() {
+        dotenv().ok();
+        assert!(ping_redis(&get_redis_client().unwrap()).await.is_ok());
+
+        let unreachable = redis::Client::open("redis://127.0.0.1:1").unwrap();
+        assert!(ping_redis(&unreachable).await.is_err());
+    }
 }
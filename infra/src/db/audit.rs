@@ -0,0 +1,227 @@
+use std::sync::Arc;
+
+use chrono::NaiveDateTime;
+use shared::types::DefaultError;
+use sqlx::{FromRow, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::db::queries::{Binds, fetch, insert};
+
+// recorded in the same transaction as the change it describes, so one can't exist without the other
+pub struct AuditEntry {
+    actor: String,
+    action: &'static str,
+    room_id: Option<i32>,
+    room_uuid: Option<Uuid>,
+    target: Option<String>,
+    detail: Option<String>,
+}
+
+impl AuditEntry {
+    pub fn new(actor: &str, action: &'static str) -> Self {
+        Self {
+            actor: actor.to_string(),
+            action,
+            room_id: None,
+            room_uuid: None,
+            target: None,
+            detail: None,
+        }
+    }
+    pub fn room(mut self, room_id: i32) -> Self {
+        self.room_id = Some(room_id);
+        self
+    }
+    pub fn room_uuid(mut self, room_uuid: Uuid) -> Self {
+        self.room_uuid = Some(room_uuid);
+        self
+    }
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+}
+
+#[derive(Default)]
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    pub room_uuid: Option<Uuid>,
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+}
+
+#[derive(FromRow)]
+pub struct AuditLog {
+    id: i64,
+    actor: String,
+    action: String,
+    room_uuid: Option<Uuid>,
+    target: Option<String>,
+    detail: Option<String>,
+    created_at: NaiveDateTime,
+}
+
+impl AuditLog {
+    pub fn get_id(&self) -> i64 {
+        self.id
+    }
+    pub fn get_actor(&self) -> String {
+        self.actor.clone()
+    }
+    pub fn get_action(&self) -> String {
+        self.action.clone()
+    }
+    pub fn get_room_uuid(&self) -> Option<Uuid> {
+        self.room_uuid
+    }
+    pub fn get_target(&self) -> Option<String> {
+        self.target.clone()
+    }
+    pub fn get_detail(&self) -> Option<String> {
+        self.detail.clone()
+    }
+    pub fn get_created_at(&self) -> NaiveDateTime {
+        self.created_at
+    }
+    pub async fn record(
+        tx: &mut Transaction<'_, Postgres>,
+        entry: AuditEntry,
+    ) -> Result<AuditLog, DefaultError> {
+        let record = insert(
+            "INSERT INTO audit_log (actor, action, room_uuid, target, detail)
+            VALUES ($1, $2, COALESCE($3, (SELECT uuid FROM room WHERE id = $4)), $5, $6)
+            RETURNING *",
+            vec![
+                Binds::String(entry.actor),
+                Binds::String(entry.action.to_string()),
+                Binds::OptUuid(entry.room_uuid),
+                Binds::OptI32(entry.room_id),
+                Binds::OptString(entry.target),
+                Binds::OptString(entry.detail),
+            ],
+            tx,
+        )
+        .await?;
+        Ok(record)
+    }
+    // newest first; `to` is exclusive
+    pub async fn query(
+        tx: Option<&mut Transaction<'_, Postgres>>,
+        db_pool: Option<Arc<PgPool>>,
+        filter: AuditFilter,
+        limit: i32,
+    ) -> Result<Vec<AuditLog>, DefaultError> {
+        let records = fetch(
+            "SELECT * FROM audit_log
+            WHERE ($1::TEXT IS NULL OR actor = $1)
+                AND ($2::UUID IS NULL OR room_uuid = $2)
+                AND ($3::TIMESTAMP IS NULL OR created_at >= $3)
+                AND ($4::TIMESTAMP IS NULL OR created_at < $4)
+            ORDER BY id DESC
+            LIMIT $5",
+            vec![
+                Binds::OptString(filter.actor),
+                Binds::OptUuid(filter.room_uuid),
+                Binds::OptTimestamp(filter.from),
+                Binds::OptTimestamp(filter.to),
+                Binds::I32(limit),
+            ],
+            tx,
+            db_pool,
+        )
+        .await?;
+        Ok(records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeDelta, Utc};
+
+    use super::{AuditEntry, AuditFilter, AuditLog};
+    use crate::{db::models::Room, test_utils::get_db_test_pool};
+
+    #[tokio::test]
+    async fn test_record_and_query() {
+        let db_pool = get_db_test_pool().await;
+        let mut tx = db_pool.begin().await.unwrap();
+
+        let room = Room::create(&mut tx, None).await.unwrap();
+        let record = AuditLog::record(
+            &mut tx,
+            AuditEntry::new("audit-test-actor", "message.delete")
+                .room(room.get_id())
+                .target("42"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(record.get_room_uuid(), Some(room.get_uuid()));
+        AuditLog::record(&mut tx, AuditEntry::new("audit-test-other", "ban.create"))
+            .await
+            .unwrap();
+
+        let by_actor = AuditLog::query(
+            Some(&mut tx),
+            None,
+            AuditFilter {
+                actor: Some("audit-test-actor".to_string()),
+                ..AuditFilter::default()
+            },
+            10,
+        )
+        .await
+        .unwrap();
+        assert_eq!(by_actor.len(), 1);
+        assert_eq!(by_actor[0].get_action(), "message.delete");
+        assert_eq!(by_actor[0].get_target().as_deref(), Some("42"));
+
+        let by_room = AuditLog::query(
+            Some(&mut tx),
+            None,
+            AuditFilter {
+                room_uuid: Some(room.get_uuid()),
+                ..AuditFilter::default()
+            },
+            10,
+        )
+        .await
+        .unwrap();
+        assert_eq!(by_room.len(), 1);
+
+        let in_future = AuditLog::query(
+            Some(&mut tx),
+            None,
+            AuditFilter {
+                actor: Some("audit-test-actor".to_string()),
+                from: Some((Utc::now() + TimeDelta::hours(1)).naive_utc()),
+                ..AuditFilter::default()
+            },
+            10,
+        )
+        .await
+        .unwrap();
+        assert!(in_future.is_empty());
+
+        tx.rollback().await.unwrap();
+    }
+    #[tokio::test]
+    async fn test_audit_log_is_append_only() {
+        let db_pool = get_db_test_pool().await;
+        let mut tx = db_pool.begin().await.unwrap();
+
+        let record = AuditLog::record(&mut tx, AuditEntry::new("audit-test-actor", "room.delete"))
+            .await
+            .unwrap();
+        let result = sqlx::query("DELETE FROM audit_log WHERE id = $1")
+            .bind(record.get_id())
+            .execute(&mut *tx)
+            .await;
+        assert!(result.is_err());
+
+        tx.rollback().await.unwrap();
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::HashSet;
+
+use shared::types::DefaultError;
+use sqlx::{PgPool, migrate::Migrator};
+
+static MIGRATOR: Migrator = sqlx::migrate!("../migrations");
+
+pub struct PendingMigration {
+    pub version: i64,
+    pub description: String,
+}
+
+pub enum MigrateAction {
+    Run,
+    Pending,
+    Check,
+}
+
+impl MigrateAction {
+    pub fn parse(name: &str) -> Result<Self, DefaultError> {
+        match name {
+            "run" => Ok(Self::Run),
+            "pending" => Ok(Self::Pending),
+            "check" => Ok(Self::Check),
+            other => Err(format!(
+                "unknown migrate command {other}, expected run, pending or check"
+            )
+            .into()),
+        }
+    }
+}
+
+// shared by `server migrate` and `chatctl migrate`, returns the lines to print
+pub async fn migrate(action: MigrateAction, pool: &PgPool) -> Result<Vec<String>, DefaultError> {
+    let lines = match action {
+        MigrateAction::Run => {
+            let applied = run_migrations(pool).await?;
+            if applied.is_empty() {
+                vec!["database schema is up to date".to_string()]
+            } else {
+                applied
+                    .iter()
+                    .map(|m| format!("applied {} {}", m.version, m.description))
+                    .collect()
+            }
+        }
+        MigrateAction::Pending => {
+            let pending = pending_migrations(pool).await?;
+            if pending.is_empty() {
+                vec!["no pending migrations".to_string()]
+            } else {
+                pending
+                    .iter()
+                    .map(|m| format!("{} {}", m.version, m.description))
+                    .collect()
+            }
+        }
+        MigrateAction::Check => {
+            verify_migrations(pool).await?;
+            vec!["database schema is up to date".to_string()]
+        }
+    };
+
+    Ok(lines)
+}
+
+pub async fn run_migrations(pool: &PgPool) -> Result<Vec<PendingMigration>, DefaultError> {
+    let pending = pending_migrations(pool).await?;
+    MIGRATOR.run(pool).await?;
+
+    Ok(pending)
+}
+
+pub async fn pending_migrations(pool: &PgPool) -> Result<Vec<PendingMigration>, DefaultError> {
+    let applied: HashSet<i64> =
+        match sqlx::query_scalar::<_, i64>("SELECT version FROM _sqlx_migrations WHERE success")
+            .fetch_all(pool)
+            .await
+        {
+            Ok(versions) => versions.into_iter().collect(),
+            // 42P01: the migrations table does not exist yet, nothing was applied
+            Err(e)
+                if e.as_database_error()
+                    .and_then(|db_error| db_error.code())
+                    .as_deref()
+                    == Some("42P01") =>
+            {
+                HashSet::new()
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+    Ok(MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration() && !applied.contains(&m.version))
+        .map(|m| PendingMigration {
+            version: m.version,
+            description: m.description.to_string(),
+        })
+        .collect())
+}
+
+pub async fn verify_migrations(pool: &PgPool) -> Result<(), DefaultError> {
+    let pending = pending_migrations(pool).await?;
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    let versions: Vec<String> = pending.iter().map(|m| m.version.to_string()).collect();
+    Err(format!(
+        "database schema is behind, {} pending migrations: {}",
+        pending.len(),
+        versions.join(", ")
+    )
+    .into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MigrateAction, migrate, pending_migrations, run_migrations, verify_migrations};
+    use crate::test_utils::get_db_test_pool;
+
+    #[tokio::test]
+    async fn test_run_migrations() {
+        let pool = get_db_test_pool().await;
+
+        run_migrations(&pool).await.unwrap();
+
+        assert!(pending_migrations(&pool).await.unwrap().is_empty());
+        assert!(verify_migrations(&pool).await.is_ok());
+    }
+    #[tokio::test]
+    async fn test_migrate() {
+        let pool = get_db_test_pool().await;
+
+        assert_eq!(
+            migrate(MigrateAction::Run, &pool).await.unwrap(),
+            vec!["database schema is up to date"]
+        );
+        assert_eq!(
+            migrate(MigrateAction::Pending, &pool).await.unwrap(),
+            vec!["no pending migrations"]
+        );
+        assert_eq!(
+            migrate(MigrateAction::Check, &pool).await.unwrap(),
+            vec!["database schema is up to date"]
+        );
+        assert!(MigrateAction::parse("rollback").is_err());
+    }
+}
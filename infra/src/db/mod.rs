@@ -1,12 +1,15 @@
 use std::{env, time::Duration};
 
-use shared::types::DefaultError;
-use sqlx::{PgPool, Postgres, migrate::MigrateDatabase, postgres::PgPoolOptions};
+use shared::{config::DatabaseConfig, types::DefaultError};
+use sqlx::{Connection, PgPool, Postgres, migrate::MigrateDatabase, postgres::PgPoolOptions};
 
+pub mod audit;
+pub mod migrations;
 pub mod models;
 pub mod queries;
+pub mod webhooks;
 
-pub async fn create_pool() -> Result<PgPool, DefaultError> {
+pub async fn create_pool(config: &DatabaseConfig) -> Result<PgPool, DefaultError> {
     let is_test_mode = cfg!(test);
 
     let (var_name, expect_err) = if is_test_mode {
@@ -16,13 +19,12 @@ pub async fn create_pool() -> Result<PgPool, DefaultError> {
     };
     let database_url = env::var(var_name).expect(expect_err);
 
-    let (min_conn, max_conn) = if is_test_mode { (0, 1) } else { (5, 30) };
     let pool = async || -> Result<PgPool, sqlx::Error> {
         PgPoolOptions::new()
-            .max_connections(max_conn)
-            .min_connections(min_conn)
-            .acquire_timeout(Duration::from_secs(2))
-            .idle_timeout(Duration::from_secs(300))
+            .max_connections(config.max_connections)
+            .min_connections(config.min_connections)
+            .acquire_timeout(Duration::from_secs(config.acquire_timeout_secs))
+            .idle_timeout(Duration::from_secs(config.idle_timeout_secs))
             .test_before_acquire(true)
             .connect(&database_url)
             .await
@@ -35,10 +37,8 @@ pub async fn create_pool() -> Result<PgPool, DefaultError> {
                 && db_error.code().unwrap_or("NaN".into()) == "3D000"
             {
                 Postgres::create_database(&database_url).await?;
-                let pool = pool().await?;
-                sqlx::migrate!("../migrations").run(&pool).await?;
 
-                return Ok(pool);
+                return Ok(pool().await?);
             }
 
             Err(e.into())
@@ -46,14 +46,46 @@ pub async fn create_pool() -> Result<PgPool, DefaultError> {
     }
 }
 
+pub async fn ping_database(pool: &PgPool) -> Result<(), sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+    conn.ping().await
+}
+
+pub async fn applied_migration_version(pool: &PgPool) -> Result<Option<i64>, sqlx::Error> {
+    sqlx::query_scalar("SELECT MAX(version) FROM _sqlx_migrations WHERE success")
+        .fetch_one(pool)
+        .await
+}
+
 #[cfg(test)]
 mod tests {
-    use super::create_pool;
+    use super::{
+        applied_migration_version, create_pool, migrations::run_migrations, ping_database,
+    };
+    use crate::test_utils::test_database_config;
     use dotenvy::dotenv;
 
     #[tokio::test]
     async fn test_create_pool() {
         dotenv().ok();
-        assert!(create_pool().await.is_ok())
+        assert!(create_pool(&test_database_config()).await.is_ok())
+    }
+    #[tokio::test]
+    async fn test_ping_database() {
+        dotenv().ok();
+        let pool = create_pool(&test_database_config()).await.unwrap();
+        assert!(ping_database(&pool).await.is_ok())
+    }
+    #[tokio::test]
+    async fn test_applied_migration_version() {
+        dotenv().ok();
+        let pool = create_pool(&test_database_config()).await.unwrap();
+        run_migrations(&pool).await.unwrap();
+        let latest = sqlx::migrate!("../migrations")
+            .iter()
+            .map(|m| m.version)
+            .max();
+
+        assert_eq!(applied_migration_version(&pool).await.unwrap(), latest)
     }
 }
@@ -4,7 +4,10 @@ use sqlx::{FromRow, PgPool, Postgres, Transaction};
 use std::sync::Arc;
 use uuid::Uuid;
 
-use crate::db::queries::{Binds, delete, fetch, insert};
+use crate::db::{
+    audit::{AuditEntry, AuditLog},
+    queries::{Binds, delete, fetch, insert},
+};
 
 #[derive(FromRow)]
 #[allow(dead_code)]
@@ -21,6 +24,9 @@ impl Room {
     pub fn get_uuid(&self) -> Uuid {
         self.uuid
     }
+    pub fn get_created_at(&self) -> NaiveDateTime {
+        self.created_at
+    }
     pub async fn create(
         tx: &mut Transaction<'_, Postgres>,
         uuid: Option<Uuid>,
@@ -60,13 +66,61 @@ impl Room {
             }
         }
     }
-    pub async fn delete(tx: &mut Transaction<'_, Postgres>, id: i32) -> Result<(), DefaultError> {
+    pub async fn stats(
+        tx: Option<&mut Transaction<'_, Postgres>>,
+        db_pool: Option<Arc<PgPool>>,
+    ) -> Result<Vec<RoomStats>, DefaultError> {
+        let records = fetch(
+            "SELECT room.uuid, room.created_at,
+                COUNT(message.id) AS message_count,
+                MAX(message.created_at) AS last_message_at
+            FROM room
+            LEFT JOIN message ON message.room_id = room.id
+            GROUP BY room.id
+            ORDER BY room.id",
+            vec![],
+            tx,
+            db_pool,
+        )
+        .await?;
+        Ok(records)
+    }
+    pub async fn delete(
+        tx: &mut Transaction<'_, Postgres>,
+        id: i32,
+        actor: &str,
+    ) -> Result<(), DefaultError> {
+        // recorded first, the entry looks up the room uuid
+        AuditLog::record(tx, AuditEntry::new(actor, "room.delete").room(id)).await?;
         delete::<Room>("DELETE FROM room WHERE id = $1", vec![Binds::I32(id)], tx).await?;
 
         Ok(())
     }
 }
 
+#[derive(FromRow)]
+pub struct RoomStats {
+    uuid: Uuid,
+    created_at: NaiveDateTime,
+    message_count: i64,
+    last_message_at: Option<NaiveDateTime>,
+}
+
+impl RoomStats {
+    pub fn get_uuid(&self) -> Uuid {
+        self.uuid
+    }
+    pub fn get_created_at(&self) -> NaiveDateTime {
+        self.created_at
+    }
+    pub fn get_message_count(&self) -> i64 {
+        self.message_count
+    }
+    pub fn get_last_message_at(&self) -> Option<NaiveDateTime> {
+        self.last_message_at
+    }
+}
+
 #[derive(FromRow)]
 #[allow(dead_code)]
 pub struct Message {
@@ -74,6 +128,7 @@ pub struct Message {
     room_id: i32,
     message: String,
     created_at: NaiveDateTime,
+    seq: Option<i64>,
 }
 
 impl Message {
@@ -83,14 +138,18 @@ impl Message {
     pub fn get_message(&self) -> String {
         self.message.clone()
     }
+    pub fn get_seq(&self) -> Option<i64> {
+        self.seq
+    }
     pub async fn create(
         tx: &mut Transaction<'_, Postgres>,
         message: String,
         room_id: i32,
+        seq: i64,
     ) -> Result<Message, DefaultError> {
         let record = insert(
-            "INSERT INTO message (message, room_id) VALUES ($1, $2) RETURNING *",
-            vec![Binds::String(message), Binds::I32(room_id)],
+            "INSERT INTO message (message, room_id, seq) VALUES ($1, $2, $3) RETURNING *",
+            vec![Binds::String(message), Binds::I32(room_id), Binds::I64(seq)],
             tx,
         )
         .await?;
@@ -111,10 +170,498 @@ impl Message {
         .await?;
         Ok(records)
     }
-    pub async fn delete(tx: &mut Transaction<'_, Postgres>, id: i32) -> Result<(), DefaultError> {
-        delete::<Message>(
-            "DELETE FROM message WHERE id = $1",
+    pub async fn read_since(
+        tx: Option<&mut Transaction<'_, Postgres>>,
+        db_pool: Option<Arc<PgPool>>,
+        room_id: i32,
+        since: i64,
+        limit: i32,
+    ) -> Result<Vec<Message>, DefaultError> {
+        let records = fetch(
+            "SELECT * FROM message WHERE room_id = $1 AND seq > $2 ORDER BY seq LIMIT $3",
+            vec![Binds::I32(room_id), Binds::I64(since), Binds::I32(limit)],
+            tx,
+            db_pool,
+        )
+        .await?;
+        Ok(records)
+    }
+    pub async fn read_latest(
+        tx: Option<&mut Transaction<'_, Postgres>>,
+        db_pool: Option<Arc<PgPool>>,
+        room_id: i32,
+    ) -> Result<Option<Message>, DefaultError> {
+        let records = fetch(
+            "SELECT * FROM message WHERE room_id = $1 AND seq IS NOT NULL ORDER BY seq DESC LIMIT 1",
+            vec![Binds::I32(room_id)],
+            tx,
+            db_pool,
+        )
+        .await?;
+        Ok(records.into_iter().next())
+    }
+    pub async fn purge(
+        tx: &mut Transaction<'_, Postgres>,
+        room_id: i32,
+        actor: &str,
+    ) -> Result<u64, DefaultError> {
+        let records: Vec<Message> = fetch(
+            "DELETE FROM message WHERE room_id = $1 RETURNING *",
+            vec![Binds::I32(room_id)],
+            Some(&mut *tx),
+            None,
+        )
+        .await?;
+        AuditLog::record(
+            tx,
+            AuditEntry::new(actor, "message.purge")
+                .room(room_id)
+                .detail(format!("{} messages", records.len())),
+        )
+        .await?;
+        Ok(records.len() as u64)
+    }
+    pub async fn delete_in_room(
+        tx: &mut Transaction<'_, Postgres>,
+        room_id: i32,
+        id: i32,
+        actor: &str,
+    ) -> Result<bool, DefaultError> {
+        let records: Vec<Message> = fetch(
+            "DELETE FROM message WHERE id = $1 AND room_id = $2 RETURNING *",
+            vec![Binds::I32(id), Binds::I32(room_id)],
+            Some(&mut *tx),
+            None,
+        )
+        .await?;
+        for record in &records {
+            record.audit_delete(tx, actor).await?;
+        }
+        Ok(!records.is_empty())
+    }
+    pub async fn delete(
+        tx: &mut Transaction<'_, Postgres>,
+        id: i32,
+        actor: &str,
+    ) -> Result<(), DefaultError> {
+        let records: Vec<Message> = fetch(
+            "DELETE FROM message WHERE id = $1 RETURNING *",
             vec![Binds::I32(id)],
+            Some(&mut *tx),
+            None,
+        )
+        .await?;
+        for record in &records {
+            record.audit_delete(tx, actor).await?;
+        }
+
+        Ok(())
+    }
+    async fn audit_delete(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        actor: &str,
+    ) -> Result<(), DefaultError> {
+        AuditLog::record(
+            tx,
+            AuditEntry::new(actor, "message.delete")
+                .room(self.room_id)
+                .target(self.id.to_string())
+                .detail(self.message.clone()),
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(FromRow)]
+#[allow(dead_code)]
+pub struct RoomRead {
+    id: i32,
+    username: String,
+    room_id: i32,
+    message_id: i32,
+    updated_at: NaiveDateTime,
+}
+
+impl RoomRead {
+    pub fn get_message_id(&self) -> i32 {
+        self.message_id
+    }
+    pub async fn upsert(
+        tx: &mut Transaction<'_, Postgres>,
+        username: String,
+        room_id: i32,
+        message_id: i32,
+    ) -> Result<Option<RoomRead>, DefaultError> {
+        // nothing is written for a message outside the room
+        let records: Vec<RoomRead> = fetch(
+            "INSERT INTO room_read (username, room_id, message_id)
+            SELECT $1, $2, $3 WHERE EXISTS (SELECT 1 FROM message WHERE id = $3 AND room_id = $2)
+            ON CONFLICT (username, room_id) DO UPDATE
+            SET message_id = GREATEST(room_read.message_id, EXCLUDED.message_id),
+                updated_at = (NOW() AT TIME ZONE 'utc')
+            RETURNING *",
+            vec![
+                Binds::String(username),
+                Binds::I32(room_id),
+                Binds::I32(message_id),
+            ],
+            Some(tx),
+            None,
+        )
+        .await?;
+        Ok(records.into_iter().next())
+    }
+    pub async fn unread_counts(
+        tx: Option<&mut Transaction<'_, Postgres>>,
+        db_pool: Option<Arc<PgPool>>,
+        username: String,
+    ) -> Result<Vec<UnreadCount>, DefaultError> {
+        let records = fetch(
+            "SELECT room.uuid AS room_uuid, COUNT(message.id) AS unread
+            FROM room_read
+            JOIN room ON room.id = room_read.room_id
+            LEFT JOIN message
+                ON message.room_id = room_read.room_id AND message.id > room_read.message_id
+            WHERE room_read.username = $1
+            GROUP BY room.uuid",
+            vec![Binds::String(username)],
+            tx,
+            db_pool,
+        )
+        .await?;
+        Ok(records)
+    }
+}
+
+#[derive(FromRow)]
+pub struct UnreadCount {
+    room_uuid: Uuid,
+    unread: i64,
+}
+
+impl UnreadCount {
+    pub fn get_room_uuid(&self) -> Uuid {
+        self.room_uuid
+    }
+    pub fn get_unread(&self) -> i64 {
+        self.unread
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BanKind {
+    User,
+    Ip,
+}
+
+impl BanKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BanKind::User => "user",
+            BanKind::Ip => "ip",
+        }
+    }
+    pub fn parse(kind: &str) -> Option<BanKind> {
+        match kind {
+            "user" => Some(BanKind::User),
+            "ip" => Some(BanKind::Ip),
+            _ => None,
+        }
+    }
+}
+
+#[derive(FromRow)]
+#[allow(dead_code)]
+pub struct Ban {
+    id: i32,
+    kind: String,
+    value: String,
+    reason: Option<String>,
+    created_at: NaiveDateTime,
+}
+
+impl Ban {
+    pub fn get_kind(&self) -> String {
+        self.kind.clone()
+    }
+    pub fn get_value(&self) -> String {
+        self.value.clone()
+    }
+    pub fn get_reason(&self) -> Option<String> {
+        self.reason.clone()
+    }
+    pub fn get_created_at(&self) -> NaiveDateTime {
+        self.created_at
+    }
+    pub async fn create(
+        tx: &mut Transaction<'_, Postgres>,
+        kind: BanKind,
+        value: String,
+        reason: Option<String>,
+        actor: &str,
+    ) -> Result<Ban, DefaultError> {
+        let mut entry =
+            AuditEntry::new(actor, "ban.create").target(format!("{}:{}", kind.as_str(), value));
+        if let Some(reason) = &reason {
+            entry = entry.detail(reason.clone());
+        }
+        AuditLog::record(tx, entry).await?;
+
+        let record = insert(
+            "INSERT INTO ban (kind, value, reason) VALUES ($1, $2, $3)
+            ON CONFLICT (kind, value) DO UPDATE SET reason = EXCLUDED.reason
+            RETURNING *",
+            vec![
+                Binds::String(kind.as_str().to_string()),
+                Binds::String(value),
+                Binds::OptString(reason),
+            ],
+            tx,
+        )
+        .await?;
+        Ok(record)
+    }
+    pub async fn read(
+        tx: Option<&mut Transaction<'_, Postgres>>,
+        db_pool: Option<Arc<PgPool>>,
+    ) -> Result<Vec<Ban>, DefaultError> {
+        let records = fetch("SELECT * FROM ban ORDER BY id", vec![], tx, db_pool).await?;
+        Ok(records)
+    }
+    pub async fn is_banned(
+        tx: Option<&mut Transaction<'_, Postgres>>,
+        db_pool: Option<Arc<PgPool>>,
+        username: String,
+        ip: String,
+    ) -> Result<bool, DefaultError> {
+        let records: Vec<Ban> = fetch(
+            "SELECT * FROM ban WHERE (kind = 'user' AND value = $1) OR (kind = 'ip' AND value = $2)",
+            vec![Binds::String(username), Binds::String(ip)],
+            tx,
+            db_pool,
+        )
+        .await?;
+        Ok(!records.is_empty())
+    }
+    pub async fn delete(
+        tx: &mut Transaction<'_, Postgres>,
+        kind: BanKind,
+        value: String,
+        actor: &str,
+    ) -> Result<bool, DefaultError> {
+        let records: Vec<Ban> = fetch(
+            "DELETE FROM ban WHERE kind = $1 AND value = $2 RETURNING *",
+            vec![
+                Binds::String(kind.as_str().to_string()),
+                Binds::String(value.clone()),
+            ],
+            Some(&mut *tx),
+            None,
+        )
+        .await?;
+        if records.is_empty() {
+            return Ok(false);
+        }
+        AuditLog::record(
+            tx,
+            AuditEntry::new(actor, "ban.delete").target(format!("{}:{}", kind.as_str(), value)),
+        )
+        .await?;
+
+        Ok(true)
+    }
+}
+
+// members have no room_role row
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Role {
+    Owner,
+    Moderator,
+    Member,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Owner => "owner",
+            Role::Moderator => "moderator",
+            Role::Member => "member",
+        }
+    }
+    pub fn parse(role: &str) -> Option<Role> {
+        match role {
+            "owner" => Some(Role::Owner),
+            "moderator" => Some(Role::Moderator),
+            "member" => Some(Role::Member),
+            _ => None,
+        }
+    }
+    pub fn rank(&self) -> u8 {
+        match self {
+            Role::Owner => 2,
+            Role::Moderator => 1,
+            Role::Member => 0,
+        }
+    }
+}
+
+#[derive(FromRow)]
+#[allow(dead_code)]
+pub struct RoomRole {
+    id: i32,
+    room_id: i32,
+    username: String,
+    role: String,
+    created_at: NaiveDateTime,
+}
+
+impl RoomRole {
+    pub async fn set(
+        tx: &mut Transaction<'_, Postgres>,
+        room_id: i32,
+        username: String,
+        role: Role,
+        actor: &str,
+    ) -> Result<(), DefaultError> {
+        AuditLog::record(
+            tx,
+            AuditEntry::new(actor, "room_role.set")
+                .room(room_id)
+                .target(username.clone())
+                .detail(role.as_str()),
+        )
+        .await?;
+
+        if role == Role::Member {
+            delete::<RoomRole>(
+                "DELETE FROM room_role WHERE room_id = $1 AND username = $2",
+                vec![Binds::I32(room_id), Binds::String(username)],
+                tx,
+            )
+            .await?;
+            return Ok(());
+        }
+
+        insert::<RoomRole>(
+            "INSERT INTO room_role (room_id, username, role) VALUES ($1, $2, $3)
+            ON CONFLICT (room_id, username) DO UPDATE SET role = EXCLUDED.role
+            RETURNING *",
+            vec![
+                Binds::I32(room_id),
+                Binds::String(username),
+                Binds::String(role.as_str().to_string()),
+            ],
+            tx,
+        )
+        .await?;
+        Ok(())
+    }
+    pub async fn role_of(
+        tx: Option<&mut Transaction<'_, Postgres>>,
+        db_pool: Option<Arc<PgPool>>,
+        room_id: i32,
+        username: String,
+    ) -> Result<Role, DefaultError> {
+        let records: Vec<RoomRole> = fetch(
+            "SELECT * FROM room_role WHERE room_id = $1 AND username = $2",
+            vec![Binds::I32(room_id), Binds::String(username)],
+            tx,
+            db_pool,
+        )
+        .await?;
+        Ok(records
+            .first()
+            .and_then(|record| Role::parse(&record.role))
+            .unwrap_or(Role::Member))
+    }
+}
+
+#[derive(FromRow)]
+#[allow(dead_code)]
+pub struct RoomBan {
+    id: i32,
+    room_id: i32,
+    username: String,
+    reason: Option<String>,
+    banned_by: String,
+    created_at: NaiveDateTime,
+}
+
+impl RoomBan {
+    pub fn get_username(&self) -> String {
+        self.username.clone()
+    }
+    pub fn get_reason(&self) -> Option<String> {
+        self.reason.clone()
+    }
+    pub fn get_banned_by(&self) -> String {
+        self.banned_by.clone()
+    }
+    pub async fn create(
+        tx: &mut Transaction<'_, Postgres>,
+        room_id: i32,
+        username: String,
+        reason: Option<String>,
+        banned_by: String,
+    ) -> Result<RoomBan, DefaultError> {
+        let mut entry = AuditEntry::new(&banned_by, "room_ban.create")
+            .room(room_id)
+            .target(username.clone());
+        if let Some(reason) = &reason {
+            entry = entry.detail(reason.clone());
+        }
+        AuditLog::record(tx, entry).await?;
+
+        let record = insert(
+            "INSERT INTO room_ban (room_id, username, reason, banned_by) VALUES ($1, $2, $3, $4)
+            ON CONFLICT (room_id, username)
+            DO UPDATE SET reason = EXCLUDED.reason, banned_by = EXCLUDED.banned_by
+            RETURNING *",
+            vec![
+                Binds::I32(room_id),
+                Binds::String(username),
+                Binds::OptString(reason),
+                Binds::String(banned_by),
+            ],
+            tx,
+        )
+        .await?;
+        Ok(record)
+    }
+    pub async fn is_banned(
+        tx: Option<&mut Transaction<'_, Postgres>>,
+        db_pool: Option<Arc<PgPool>>,
+        room_id: i32,
+        username: String,
+    ) -> Result<bool, DefaultError> {
+        let records: Vec<RoomBan> = fetch(
+            "SELECT * FROM room_ban WHERE room_id = $1 AND username = $2",
+            vec![Binds::I32(room_id), Binds::String(username)],
+            tx,
+            db_pool,
+        )
+        .await?;
+        Ok(!records.is_empty())
+    }
+    pub async fn delete(
+        tx: &mut Transaction<'_, Postgres>,
+        room_id: i32,
+        username: String,
+        actor: &str,
+    ) -> Result<(), DefaultError> {
+        AuditLog::record(
+            tx,
+            AuditEntry::new(actor, "room_ban.delete")
+                .room(room_id)
+                .target(username.clone()),
+        )
+        .await?;
+        delete::<RoomBan>(
+            "DELETE FROM room_ban WHERE room_id = $1 AND username = $2",
+            vec![Binds::I32(room_id), Binds::String(username)],
             tx,
         )
         .await?;
@@ -127,7 +674,7 @@ impl Message {
 mod tests {
     use shared::helpers::generate_uuid_v4;
 
-    use super::{Message, Room};
+    use super::{Ban, BanKind, Message, Role, Room, RoomBan, RoomRead, RoomRole};
     use crate::test_utils::get_db_test_pool;
 
     #[tokio::test]
@@ -189,7 +736,7 @@ mod tests {
 
         let record = Room::create(&mut tx, None).await.unwrap();
 
-        let result = Room::delete(&mut tx, record.get_id()).await;
+        let result = Room::delete(&mut tx, record.get_id(), "test").await;
         assert!(result.is_ok());
 
         tx.rollback().await.unwrap();
@@ -200,7 +747,8 @@ mod tests {
         let mut tx = db_pool.begin().await.unwrap();
 
         let some_room = Room::create(&mut tx, None).await.unwrap();
-        let record = Message::create(&mut tx, "hello-rust".to_string(), some_room.get_id()).await;
+        let record =
+            Message::create(&mut tx, "hello-rust".to_string(), some_room.get_id(), 1).await;
         assert!(record.is_ok());
         assert_eq!(record.unwrap().get_message(), "hello-rust");
 
@@ -212,7 +760,7 @@ mod tests {
         let mut tx = db_pool.begin().await.unwrap();
 
         let some_room_id = Room::create(&mut tx, None).await.unwrap().get_id();
-        Message::create(&mut tx, "hello-rust-2".to_string(), some_room_id)
+        Message::create(&mut tx, "hello-rust-2".to_string(), some_room_id, 1)
             .await
             .unwrap();
 
@@ -229,8 +777,8 @@ mod tests {
 
         let some_room_id = Room::create(&mut tx, None).await.unwrap().get_id();
 
-        for _ in 0..=2 {
-            Message::create(&mut tx, "hello-rust-3".to_string(), some_room_id)
+        for seq in 1..=3 {
+            Message::create(&mut tx, "hello-rust-3".to_string(), some_room_id, seq)
                 .await
                 .unwrap();
         }
@@ -247,12 +795,320 @@ mod tests {
         let mut tx = db_pool.begin().await.unwrap();
 
         let some_room = Room::create(&mut tx, None).await.unwrap();
-        let message = Message::create(&mut tx, "hello-rust-love".to_string(), some_room.get_id())
+        let message = Message::create(
+            &mut tx,
+            "hello-rust-love".to_string(),
+            some_room.get_id(),
+            1,
+        )
+        .await
+        .unwrap();
+
+        let result = Message::delete(&mut tx, message.get_id(), "test").await;
+        assert!(result.is_ok());
+
+        tx.rollback().await.unwrap();
+    }
+    #[tokio::test]
+    async fn test_delete_message_in_room() {
+        let db_pool = get_db_test_pool().await;
+        let mut tx = db_pool.begin().await.unwrap();
+
+        let room_id = Room::create(&mut tx, None).await.unwrap().get_id();
+        let other_room_id = Room::create(&mut tx, None).await.unwrap().get_id();
+        let message_id = Message::create(&mut tx, "hello".to_string(), room_id, 1)
             .await
+            .unwrap()
+            .get_id();
+
+        assert!(
+            !Message::delete_in_room(&mut tx, other_room_id, message_id, "test")
+                .await
+                .unwrap()
+        );
+        assert!(
+            Message::delete_in_room(&mut tx, room_id, message_id, "test")
+                .await
+                .unwrap()
+        );
+        assert!(
+            Message::read(Some(&mut tx), None, room_id, 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        tx.rollback().await.unwrap();
+    }
+    #[tokio::test]
+    async fn test_room_stats() {
+        let db_pool = get_db_test_pool().await;
+        let mut tx = db_pool.begin().await.unwrap();
+
+        let room = Room::create(&mut tx, None).await.unwrap();
+        for seq in 1..=3 {
+            Message::create(&mut tx, "hello".to_string(), room.get_id(), seq)
+                .await
+                .unwrap();
+        }
+        let empty_room = Room::create(&mut tx, None).await.unwrap();
+
+        let stats = Room::stats(Some(&mut tx), None).await.unwrap();
+        let room_stats = stats
+            .iter()
+            .find(|s| s.get_uuid() == room.get_uuid())
             .unwrap();
+        assert_eq!(room_stats.get_message_count(), 3);
+        assert!(room_stats.get_last_message_at().is_some());
 
-        let result = Message::delete(&mut tx, message.get_id()).await;
-        assert!(result.is_ok());
+        let empty_stats = stats
+            .iter()
+            .find(|s| s.get_uuid() == empty_room.get_uuid())
+            .unwrap();
+        assert_eq!(empty_stats.get_message_count(), 0);
+        assert!(empty_stats.get_last_message_at().is_none());
+
+        tx.rollback().await.unwrap();
+    }
+    #[tokio::test]
+    async fn test_upsert_room_read() {
+        let db_pool = get_db_test_pool().await;
+        let mut tx = db_pool.begin().await.unwrap();
+
+        let room_id = Room::create(&mut tx, None).await.unwrap().get_id();
+        let first = Message::create(&mut tx, "hello-read-1".to_string(), room_id, 1)
+            .await
+            .unwrap();
+        let second = Message::create(&mut tx, "hello-read-2".to_string(), room_id, 2)
+            .await
+            .unwrap();
+
+        let record = RoomRead::upsert(&mut tx, "reader".to_string(), room_id, second.get_id())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.get_message_id(), second.get_id());
+
+        // moving the pointer backwards keeps the newest read message
+        let record = RoomRead::upsert(&mut tx, "reader".to_string(), room_id, first.get_id())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.get_message_id(), second.get_id());
+
+        tx.rollback().await.unwrap();
+    }
+    #[tokio::test]
+    async fn test_upsert_room_read_message_from_other_room() {
+        let db_pool = get_db_test_pool().await;
+        let mut tx = db_pool.begin().await.unwrap();
+
+        let room_id = Room::create(&mut tx, None).await.unwrap().get_id();
+        let other_room_id = Room::create(&mut tx, None).await.unwrap().get_id();
+        let message = Message::create(&mut tx, "hello-read-3".to_string(), other_room_id, 1)
+            .await
+            .unwrap();
+
+        let result =
+            RoomRead::upsert(&mut tx, "reader".to_string(), room_id, message.get_id()).await;
+        assert!(result.unwrap().is_none());
+
+        tx.rollback().await.unwrap();
+    }
+    #[tokio::test]
+    async fn test_unread_counts() {
+        let db_pool = get_db_test_pool().await;
+        let mut tx = db_pool.begin().await.unwrap();
+
+        let room = Room::create(&mut tx, None).await.unwrap();
+        let read = Message::create(&mut tx, "hello-read-4".to_string(), room.get_id(), 1)
+            .await
+            .unwrap();
+        for seq in 2..=4 {
+            Message::create(&mut tx, "hello-read-5".to_string(), room.get_id(), seq)
+                .await
+                .unwrap();
+        }
+        RoomRead::upsert(&mut tx, "counter".to_string(), room.get_id(), read.get_id())
+            .await
+            .unwrap();
+
+        let counts = RoomRead::unread_counts(Some(&mut tx), None, "counter".to_string())
+            .await
+            .unwrap();
+        assert_eq!(counts.len(), 1);
+        assert_eq!(counts[0].get_room_uuid(), room.get_uuid());
+        assert_eq!(counts[0].get_unread(), 3);
+
+        tx.rollback().await.unwrap();
+    }
+    #[tokio::test]
+    async fn test_read_messages_since() {
+        let db_pool = get_db_test_pool().await;
+        let mut tx = db_pool.begin().await.unwrap();
+
+        let some_room_id = Room::create(&mut tx, None).await.unwrap().get_id();
+        for seq in 1..=5 {
+            Message::create(&mut tx, "hello-since".to_string(), some_room_id, seq)
+                .await
+                .unwrap();
+        }
+
+        let records = Message::read_since(Some(&mut tx), None, some_room_id, 3, 10)
+            .await
+            .unwrap();
+        assert_eq!(
+            records.iter().map(|m| m.get_seq()).collect::<Vec<_>>(),
+            vec![Some(4), Some(5)]
+        );
+
+        let latest = Message::read_latest(Some(&mut tx), None, some_room_id)
+            .await
+            .unwrap();
+        assert_eq!(latest.unwrap().get_seq(), Some(5));
+
+        tx.rollback().await.unwrap();
+    }
+    #[tokio::test]
+    async fn test_purge_messages() {
+        let db_pool = get_db_test_pool().await;
+        let mut tx = db_pool.begin().await.unwrap();
+
+        let room = Room::create(&mut tx, None).await.unwrap();
+        for seq in 1..=3 {
+            Message::create(&mut tx, "hello".to_string(), room.get_id(), seq)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            Message::purge(&mut tx, room.get_id(), "test")
+                .await
+                .unwrap(),
+            3
+        );
+        assert!(
+            Message::read(Some(&mut tx), None, room.get_id(), 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        tx.rollback().await.unwrap();
+    }
+    #[tokio::test]
+    async fn test_ban() {
+        let db_pool = get_db_test_pool().await;
+        let mut tx = db_pool.begin().await.unwrap();
+        let username = format!("anonymous_{}", generate_uuid_v4().simple());
+
+        let record = Ban::create(
+            &mut tx,
+            BanKind::User,
+            username.clone(),
+            Some("spam".to_string()),
+            "test",
+        )
+        .await
+        .unwrap();
+        assert_eq!(record.get_kind(), "user");
+        assert_eq!(record.get_reason().as_deref(), Some("spam"));
+
+        assert!(
+            Ban::is_banned(
+                Some(&mut tx),
+                None,
+                username.clone(),
+                "10.0.0.1".to_string()
+            )
+            .await
+            .unwrap()
+        );
+        assert!(
+            !Ban::is_banned(Some(&mut tx), None, "someone".to_string(), username.clone())
+                .await
+                .unwrap()
+        );
+
+        assert!(
+            Ban::delete(&mut tx, BanKind::User, username.clone(), "test")
+                .await
+                .unwrap()
+        );
+        assert!(
+            !Ban::delete(&mut tx, BanKind::User, username.clone(), "test")
+                .await
+                .unwrap()
+        );
+        assert!(
+            !Ban::is_banned(Some(&mut tx), None, username, "10.0.0.1".to_string())
+                .await
+                .unwrap()
+        );
+
+        tx.rollback().await.unwrap();
+    }
+    #[tokio::test]
+    async fn test_room_role() {
+        let db_pool = get_db_test_pool().await;
+        let mut tx = db_pool.begin().await.unwrap();
+
+        let room_id = Room::create(&mut tx, None).await.unwrap().get_id();
+        let username = "anonymous_role".to_string();
+
+        for role in [Role::Moderator, Role::Member] {
+            RoomRole::set(&mut tx, room_id, username.clone(), role, "test")
+                .await
+                .unwrap();
+            assert_eq!(
+                RoomRole::role_of(Some(&mut tx), None, room_id, username.clone())
+                    .await
+                    .unwrap(),
+                role
+            );
+        }
+
+        tx.rollback().await.unwrap();
+    }
+    #[tokio::test]
+    async fn test_room_ban() {
+        let db_pool = get_db_test_pool().await;
+        let mut tx = db_pool.begin().await.unwrap();
+
+        let room_id = Room::create(&mut tx, None).await.unwrap().get_id();
+        let other_room_id = Room::create(&mut tx, None).await.unwrap().get_id();
+        let username = "anonymous_room_ban".to_string();
+
+        let ban = RoomBan::create(
+            &mut tx,
+            room_id,
+            username.clone(),
+            Some("spam".to_string()),
+            "anonymous_owner".to_string(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(ban.get_banned_by(), "anonymous_owner");
+
+        assert!(
+            RoomBan::is_banned(Some(&mut tx), None, room_id, username.clone())
+                .await
+                .unwrap()
+        );
+        assert!(
+            !RoomBan::is_banned(Some(&mut tx), None, other_room_id, username.clone())
+                .await
+                .unwrap()
+        );
+
+        RoomBan::delete(&mut tx, room_id, username.clone(), "test")
+            .await
+            .unwrap();
+        assert!(
+            !RoomBan::is_banned(Some(&mut tx), None, room_id, username)
+                .await
+                .unwrap()
+        );
 
         tx.rollback().await.unwrap();
     }
@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use chrono::NaiveDateTime;
 use sqlx::{
     FromRow, PgPool, Postgres, Transaction,
     postgres::{PgArguments, PgRow},
@@ -9,12 +10,18 @@ use uuid::Uuid;
 
 pub enum Binds {
     String(String),
+    OptString(Option<String>),
+    Strings(Vec<String>),
     I32(i32),
+    OptI32(Option<i32>),
     I64(i64),
     Bool(bool),
     Uuid(Uuid),
+    OptUuid(Option<Uuid>),
+    OptTimestamp(Option<NaiveDateTime>),
 }
 
+#[tracing::instrument(name = "db.query", level = "debug", skip_all, fields(db.system = "postgresql", db.operation = "insert", db.statement = sql))]
 pub async fn insert<M>(
     sql: &str,
     binds: Vec<Binds>,
@@ -30,6 +37,7 @@ where
     Ok(query)
 }
 
+#[tracing::instrument(name = "db.query", level = "debug", skip_all, fields(db.system = "postgresql", db.operation = "fetch", db.statement = sql))]
 pub async fn fetch<M>(
     sql: &str,
     binds: Vec<Binds>,
@@ -53,6 +61,7 @@ where
     }
 }
 
+#[tracing::instrument(name = "db.query", level = "debug", skip_all, fields(db.system = "postgresql", db.operation = "delete", db.statement = sql))]
 pub async fn delete<M>(
     sql: &str,
     binds: Vec<Binds>,
@@ -80,9 +89,18 @@ where
             Binds::String(v) => {
                 query = query.bind(v);
             }
+            Binds::OptString(v) => {
+                query = query.bind(v);
+            }
+            Binds::Strings(v) => {
+                query = query.bind(v);
+            }
             Binds::I32(v) => {
                 query = query.bind(v);
             }
+            Binds::OptI32(v) => {
+                query = query.bind(v);
+            }
             Binds::I64(v) => {
                 query = query.bind(v);
             }
@@ -92,6 +110,12 @@ where
             Binds::Uuid(v) => {
                 query = query.bind(v);
             }
+            Binds::OptUuid(v) => {
+                query = query.bind(v);
+            }
+            Binds::OptTimestamp(v) => {
+                query = query.bind(v);
+            }
         };
     }
 
@@ -0,0 +1,473 @@
+use std::sync::Arc;
+
+use chrono::NaiveDateTime;
+use shared::types::DefaultError;
+use sqlx::{FromRow, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::db::{
+    audit::{AuditEntry, AuditLog},
+    queries::{Binds, fetch, insert},
+};
+
+pub const EVENTS: [&str; 3] = ["room.created", "member.joined", "message.created"];
+
+#[derive(FromRow)]
+pub struct WebhookSubscription {
+    id: i32,
+    url: String,
+    secret: String,
+    room_uuid: Option<Uuid>,
+    events: Vec<String>,
+    created_by: String,
+    created_at: NaiveDateTime,
+}
+
+impl WebhookSubscription {
+    pub fn get_id(&self) -> i32 {
+        self.id
+    }
+    pub fn get_url(&self) -> String {
+        self.url.clone()
+    }
+    pub fn get_secret(&self) -> String {
+        self.secret.clone()
+    }
+    pub fn get_room_uuid(&self) -> Option<Uuid> {
+        self.room_uuid
+    }
+    pub fn get_events(&self) -> Vec<String> {
+        self.events.clone()
+    }
+    pub fn get_created_by(&self) -> String {
+        self.created_by.clone()
+    }
+    pub fn get_created_at(&self) -> NaiveDateTime {
+        self.created_at
+    }
+    pub async fn create(
+        tx: &mut Transaction<'_, Postgres>,
+        url: String,
+        secret: String,
+        room_uuid: Option<Uuid>,
+        events: Vec<String>,
+        actor: &str,
+    ) -> Result<WebhookSubscription, DefaultError> {
+        let record: WebhookSubscription = insert(
+            "INSERT INTO webhook_subscription (url, secret, room_uuid, events, created_by)
+            VALUES ($1, $2, $3, $4, $5) RETURNING *",
+            vec![
+                Binds::String(url),
+                Binds::String(secret),
+                Binds::OptUuid(room_uuid),
+                Binds::Strings(events),
+                Binds::String(actor.to_string()),
+            ],
+            tx,
+        )
+        .await?;
+
+        let mut entry = AuditEntry::new(actor, "webhook.create")
+            .target(record.id.to_string())
+            .detail(record.url.clone());
+        if let Some(room_uuid) = room_uuid {
+            entry = entry.room_uuid(room_uuid);
+        }
+        AuditLog::record(tx, entry).await?;
+
+        Ok(record)
+    }
+    pub async fn read(
+        tx: Option<&mut Transaction<'_, Postgres>>,
+        db_pool: Option<Arc<PgPool>>,
+    ) -> Result<Vec<WebhookSubscription>, DefaultError> {
+        let records = fetch(
+            "SELECT * FROM webhook_subscription ORDER BY id",
+            vec![],
+            tx,
+            db_pool,
+        )
+        .await?;
+        Ok(records)
+    }
+    // pending deliveries and dead letters of the subscription go with it
+    pub async fn delete(
+        tx: &mut Transaction<'_, Postgres>,
+        id: i32,
+        actor: &str,
+    ) -> Result<bool, DefaultError> {
+        let records: Vec<WebhookSubscription> = fetch(
+            "DELETE FROM webhook_subscription WHERE id = $1 RETURNING *",
+            vec![Binds::I32(id)],
+            Some(&mut *tx),
+            None,
+        )
+        .await?;
+        for record in &records {
+            AuditLog::record(
+                tx,
+                AuditEntry::new(actor, "webhook.delete")
+                    .target(record.id.to_string())
+                    .detail(record.url.clone()),
+            )
+            .await?;
+        }
+        Ok(!records.is_empty())
+    }
+}
+
+// a queued delivery together with the url and secret of its subscription
+#[derive(FromRow)]
+pub struct WebhookDelivery {
+    id: i64,
+    subscription_id: i32,
+    event: String,
+    payload: String,
+    attempts: i32,
+    last_error: Option<String>,
+    next_attempt_at: NaiveDateTime,
+    created_at: NaiveDateTime,
+    url: String,
+    secret: String,
+}
+
+impl WebhookDelivery {
+    pub fn get_id(&self) -> i64 {
+        self.id
+    }
+    pub fn get_subscription_id(&self) -> i32 {
+        self.subscription_id
+    }
+    pub fn get_event(&self) -> String {
+        self.event.clone()
+    }
+    pub fn get_payload(&self) -> String {
+        self.payload.clone()
+    }
+    pub fn get_attempts(&self) -> i32 {
+        self.attempts
+    }
+    pub fn get_last_error(&self) -> Option<String> {
+        self.last_error.clone()
+    }
+    pub fn get_next_attempt_at(&self) -> NaiveDateTime {
+        self.next_attempt_at
+    }
+    pub fn get_created_at(&self) -> NaiveDateTime {
+        self.created_at
+    }
+    pub fn get_url(&self) -> String {
+        self.url.clone()
+    }
+    pub fn get_secret(&self) -> String {
+        self.secret.clone()
+    }
+    // one delivery per matching subscription, returns how many were queued
+    pub async fn enqueue(
+        tx: &mut Transaction<'_, Postgres>,
+        event: &str,
+        room_uuid: Uuid,
+        payload: String,
+    ) -> Result<u64, DefaultError> {
+        let records: Vec<(i64,)> = fetch(
+            "INSERT INTO webhook_delivery (subscription_id, event, payload)
+            SELECT id, $1, $2 FROM webhook_subscription
+            WHERE (room_uuid IS NULL OR room_uuid = $3)
+                AND (cardinality(events) = 0 OR $1 = ANY(events))
+            RETURNING id",
+            vec![
+                Binds::String(event.to_string()),
+                Binds::String(payload),
+                Binds::Uuid(room_uuid),
+            ],
+            Some(tx),
+            None,
+        )
+        .await?;
+        Ok(records.len() as u64)
+    }
+    // due deliveries are pushed `lease_secs` into the future so other workers skip them;
+    // if this worker dies before finishing, they come due again after the lease
+    pub async fn claim(
+        db_pool: Arc<PgPool>,
+        limit: i32,
+        lease_secs: i32,
+    ) -> Result<Vec<WebhookDelivery>, DefaultError> {
+        let records = fetch(
+            "WITH due AS (
+                SELECT id FROM webhook_delivery
+                WHERE next_attempt_at <= (NOW() AT TIME ZONE 'utc')
+                ORDER BY id
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            ), claimed AS (
+                UPDATE webhook_delivery
+                SET next_attempt_at = (NOW() AT TIME ZONE 'utc') + $2 * INTERVAL '1 second'
+                FROM due
+                WHERE webhook_delivery.id = due.id
+                RETURNING webhook_delivery.*
+            )
+            SELECT claimed.*, subscription.url, subscription.secret
+            FROM claimed
+            JOIN webhook_subscription subscription ON subscription.id = claimed.subscription_id
+            ORDER BY claimed.id",
+            vec![Binds::I32(limit), Binds::I32(lease_secs)],
+            None,
+            Some(db_pool),
+        )
+        .await?;
+        Ok(records)
+    }
+    pub async fn complete(tx: &mut Transaction<'_, Postgres>, id: i64) -> Result<(), DefaultError> {
+        let _: Vec<(i64,)> = fetch(
+            "DELETE FROM webhook_delivery WHERE id = $1 RETURNING id",
+            vec![Binds::I64(id)],
+            Some(tx),
+            None,
+        )
+        .await?;
+        Ok(())
+    }
+    pub async fn retry(
+        tx: &mut Transaction<'_, Postgres>,
+        id: i64,
+        error: String,
+        delay_secs: i32,
+    ) -> Result<(), DefaultError> {
+        let _: Vec<(i64,)> = fetch(
+            "UPDATE webhook_delivery
+            SET attempts = attempts + 1,
+                last_error = $2,
+                next_attempt_at = (NOW() AT TIME ZONE 'utc') + $3 * INTERVAL '1 second'
+            WHERE id = $1
+            RETURNING id",
+            vec![Binds::I64(id), Binds::String(error), Binds::I32(delay_secs)],
+            Some(tx),
+            None,
+        )
+        .await?;
+        Ok(())
+    }
+    // moves the delivery to the dead letter table, counting this last attempt
+    pub async fn dead_letter(
+        tx: &mut Transaction<'_, Postgres>,
+        id: i64,
+        error: String,
+    ) -> Result<Option<WebhookDeadLetter>, DefaultError> {
+        let mut records: Vec<WebhookDeadLetter> = fetch(
+            "WITH failed AS (DELETE FROM webhook_delivery WHERE id = $1 RETURNING *)
+            INSERT INTO webhook_dead_letter
+                (subscription_id, event, payload, attempts, last_error, created_at)
+            SELECT subscription_id, event, payload, attempts + 1, $2, created_at FROM failed
+            RETURNING *",
+            vec![Binds::I64(id), Binds::String(error)],
+            Some(tx),
+            None,
+        )
+        .await?;
+        Ok(records.pop())
+    }
+}
+
+#[derive(FromRow)]
+pub struct WebhookDeadLetter {
+    id: i64,
+    subscription_id: i32,
+    event: String,
+    payload: String,
+    attempts: i32,
+    last_error: Option<String>,
+    created_at: NaiveDateTime,
+    failed_at: NaiveDateTime,
+}
+
+impl WebhookDeadLetter {
+    pub fn get_id(&self) -> i64 {
+        self.id
+    }
+    pub fn get_subscription_id(&self) -> i32 {
+        self.subscription_id
+    }
+    pub fn get_event(&self) -> String {
+        self.event.clone()
+    }
+    pub fn get_payload(&self) -> String {
+        self.payload.clone()
+    }
+    pub fn get_attempts(&self) -> i32 {
+        self.attempts
+    }
+    pub fn get_last_error(&self) -> Option<String> {
+        self.last_error.clone()
+    }
+    pub fn get_created_at(&self) -> NaiveDateTime {
+        self.created_at
+    }
+    pub fn get_failed_at(&self) -> NaiveDateTime {
+        self.failed_at
+    }
+    // newest first
+    pub async fn read(
+        tx: Option<&mut Transaction<'_, Postgres>>,
+        db_pool: Option<Arc<PgPool>>,
+        limit: i32,
+    ) -> Result<Vec<WebhookDeadLetter>, DefaultError> {
+        let records = fetch(
+            "SELECT * FROM webhook_dead_letter ORDER BY id DESC LIMIT $1",
+            vec![Binds::I32(limit)],
+            tx,
+            db_pool,
+        )
+        .await?;
+        Ok(records)
+    }
+    // queues the payload again with a fresh attempt count
+    pub async fn requeue(
+        tx: &mut Transaction<'_, Postgres>,
+        id: i64,
+        actor: &str,
+    ) -> Result<bool, DefaultError> {
+        let records: Vec<(i64,)> = fetch(
+            "WITH retried AS (DELETE FROM webhook_dead_letter WHERE id = $1 RETURNING *)
+            INSERT INTO webhook_delivery (subscription_id, event, payload)
+            SELECT subscription_id, event, payload FROM retried
+            RETURNING id",
+            vec![Binds::I64(id)],
+            Some(&mut *tx),
+            None,
+        )
+        .await?;
+        if records.is_empty() {
+            return Ok(false);
+        }
+        AuditLog::record(
+            tx,
+            AuditEntry::new(actor, "webhook.requeue").target(id.to_string()),
+        )
+        .await?;
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::{WebhookDeadLetter, WebhookDelivery, WebhookSubscription};
+    use crate::test_utils::get_db_test_pool;
+
+    #[tokio::test]
+    async fn test_enqueue_matches_subscriptions() {
+        let db_pool = get_db_test_pool().await;
+        let mut tx = db_pool.begin().await.unwrap();
+        let room_uuid = Uuid::new_v4();
+
+        let all_events = WebhookSubscription::create(
+            &mut tx,
+            "http://127.0.0.1:1/all".to_string(),
+            "webhook-test-secret".to_string(),
+            Some(room_uuid),
+            vec![],
+            "test",
+        )
+        .await
+        .unwrap();
+        WebhookSubscription::create(
+            &mut tx,
+            "http://127.0.0.1:1/joins".to_string(),
+            "webhook-test-secret".to_string(),
+            Some(room_uuid),
+            vec!["member.joined".to_string()],
+            "test",
+        )
+        .await
+        .unwrap();
+        WebhookSubscription::create(
+            &mut tx,
+            "http://127.0.0.1:1/other-room".to_string(),
+            "webhook-test-secret".to_string(),
+            Some(Uuid::new_v4()),
+            vec![],
+            "test",
+        )
+        .await
+        .unwrap();
+
+        let queued =
+            WebhookDelivery::enqueue(&mut tx, "message.created", room_uuid, "{}".to_string())
+                .await
+                .unwrap();
+        assert_eq!(queued, 1);
+        let queued =
+            WebhookDelivery::enqueue(&mut tx, "member.joined", room_uuid, "{}".to_string())
+                .await
+                .unwrap();
+        assert_eq!(queued, 2);
+
+        assert!(
+            WebhookSubscription::delete(&mut tx, all_events.get_id(), "test")
+                .await
+                .unwrap()
+        );
+        assert!(
+            !WebhookSubscription::delete(&mut tx, all_events.get_id(), "test")
+                .await
+                .unwrap()
+        );
+
+        tx.rollback().await.unwrap();
+    }
+    #[tokio::test]
+    async fn test_dead_letter_and_requeue() {
+        let db_pool = get_db_test_pool().await;
+        let mut tx = db_pool.begin().await.unwrap();
+        let room_uuid = Uuid::new_v4();
+
+        WebhookSubscription::create(
+            &mut tx,
+            "http://127.0.0.1:1/hook".to_string(),
+            "webhook-test-secret".to_string(),
+            Some(room_uuid),
+            vec![],
+            "test",
+        )
+        .await
+        .unwrap();
+        WebhookDelivery::enqueue(&mut tx, "room.created", room_uuid, "{}".to_string())
+            .await
+            .unwrap();
+        let (delivery_id,): (i64,) = sqlx::query_as(
+            "SELECT d.id FROM webhook_delivery d
+            JOIN webhook_subscription s ON s.id = d.subscription_id
+            WHERE s.room_uuid = $1",
+        )
+        .bind(room_uuid)
+        .fetch_one(&mut *tx)
+        .await
+        .unwrap();
+
+        WebhookDelivery::retry(&mut tx, delivery_id, "timed out".to_string(), 60)
+            .await
+            .unwrap();
+        let dead_letter = WebhookDelivery::dead_letter(&mut tx, delivery_id, "500".to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(dead_letter.get_attempts(), 2);
+        assert_eq!(dead_letter.get_last_error().as_deref(), Some("500"));
+
+        assert!(
+            WebhookDeadLetter::requeue(&mut tx, dead_letter.get_id(), "test")
+                .await
+                .unwrap()
+        );
+        assert!(
+            !WebhookDeadLetter::requeue(&mut tx, dead_letter.get_id(), "test")
+                .await
+                .unwrap()
+        );
+
+        tx.rollback().await.unwrap();
+    }
+}
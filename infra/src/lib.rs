@@ -1,6 +1,7 @@
 pub mod cache;
 pub mod db;
 pub mod logging;
+pub mod telemetry;
 
 #[cfg(test)]
 mod test_utils;
@@ -1,20 +1,114 @@
-use std::io::Write;
+use std::{env, future::Future, io::Write};
 
-use chrono::Local;
+use chrono::{Local, Utc};
+use log::Record;
+use serde_json::json;
+use shared::config::{LogConfig, LogFormat};
+
+tokio::task_local! {
+    static CORRELATION_ID: String;
+}
+
+pub fn init_logger(config: &LogConfig) {
+    let filters = log_filters(config);
+    let format = config.format;
 
-pub fn init_logger() {
     env_logger::builder()
-        .format(|buf, record| {
-            writeln!(
-                buf,
-                "[{}] [{}] [{}:{}]: {}",
-                Local::now().format("%F %T"),
-                record.level(),
-                record.file().unwrap_or("NaN"),
-                record.line().unwrap_or(0),
-                record.args()
-            )
-        })
-        .filter_level(log::LevelFilter::Info)
+        .format(move |buf, record| writeln!(buf, "{}", format_record(format, record)))
+        .parse_filters(&filters)
         .init();
 }
+
+// env_logger filter syntax, which tracing's EnvFilter reads as well
+pub fn log_filters(config: &LogConfig) -> String {
+    env::var("RUST_LOG").unwrap_or_else(|_| config.level.clone())
+}
+
+pub fn with_correlation_id<F: Future>(id: String, fut: F) -> impl Future<Output = F::Output> {
+    CORRELATION_ID.scope(id, fut)
+}
+
+pub fn correlation_id() -> Option<String> {
+    CORRELATION_ID.try_with(|id| id.clone()).ok()
+}
+
+fn format_record(format: LogFormat, record: &Record) -> String {
+    let correlation_id = correlation_id();
+
+    match format {
+        LogFormat::Text => format!(
+            "[{}] [{}] [{}:{}]{}: {}",
+            Local::now().format("%F %T"),
+            record.level(),
+            record.file().unwrap_or("NaN"),
+            record.line().unwrap_or(0),
+            correlation_id
+                .map(|id| format!(" [{}]", id))
+                .unwrap_or_default(),
+            record.args()
+        ),
+        LogFormat::Json => {
+            let mut line = json!({
+                "timestamp": Utc::now().to_rfc3339(),
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "file": record.file(),
+                "line": record.line(),
+                "message": record.args().to_string(),
+            });
+            if let Some(id) = correlation_id {
+                line["correlation_id"] = id.into();
+            }
+            line.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{correlation_id, format_record, with_correlation_id};
+    use log::{Level, Record};
+    use serde_json::Value;
+    use shared::config::LogFormat;
+
+    fn render(format: LogFormat) -> String {
+        format_record(
+            format,
+            &Record::builder()
+                .args(format_args!("room created"))
+                .level(Level::Info)
+                .target("server")
+                .file(Some("server/src/handlers/room.rs"))
+                .line(Some(10))
+                .build(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_correlation_id_scope() {
+        assert_eq!(correlation_id(), None);
+
+        let id = with_correlation_id("req-1".to_string(), async { correlation_id() }).await;
+        assert_eq!(id.as_deref(), Some("req-1"));
+    }
+    #[tokio::test]
+    async fn test_format_record_text() {
+        let line =
+            with_correlation_id("req-2".to_string(), async { render(LogFormat::Text) }).await;
+
+        assert!(line.ends_with("[INFO] [server/src/handlers/room.rs:10] [req-2]: room created"));
+    }
+    #[tokio::test]
+    async fn test_format_record_json() {
+        let line =
+            with_correlation_id("req-3".to_string(), async { render(LogFormat::Json) }).await;
+        let parsed: Value = serde_json::from_str(&line).unwrap();
+
+        assert_eq!(parsed["level"], "INFO");
+        assert_eq!(parsed["message"], "room created");
+        assert_eq!(parsed["correlation_id"], "req-3");
+
+        let parsed: Value = serde_json::from_str(&render(LogFormat::Json)).unwrap();
+        assert!(parsed.get("correlation_id").is_none());
+    }
+}
@@ -0,0 +1,152 @@
+use std::time::Duration;
+
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{
+    Resource,
+    trace::{Sampler, SdkTracerProvider},
+};
+use shared::{
+    config::{LogConfig, TelemetryConfig},
+    types::DefaultError,
+};
+use tracing_subscriber::{EnvFilter, Registry, layer::SubscriberExt};
+
+use crate::logging::log_filters;
+
+// this crate's and the server's spans are debug level, so they stay out of the logs
+// when no subscriber is installed; they are exported whatever the log level
+const OWN_SPANS: &str = "server=debug,infra=debug";
+
+pub fn init_tracing(
+    config: &TelemetryConfig,
+    log_config: &LogConfig,
+) -> Result<Option<SdkTracerProvider>, DefaultError> {
+    if !config.enabled {
+        return Ok(None);
+    }
+
+    let provider = build_tracer_provider(config)?;
+    tracing::subscriber::set_global_default(tracing_subscriber_for(
+        &provider,
+        config,
+        &log_filters(log_config),
+    ))?;
+
+    Ok(Some(provider))
+}
+
+pub fn build_tracer_provider(config: &TelemetryConfig) -> Result<SdkTracerProvider, DefaultError> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(config.otlp_endpoint.clone())
+        .with_timeout(Duration::from_secs(config.export_timeout_secs))
+        .build()?;
+
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+            config.sample_ratio,
+        ))))
+        .with_resource(
+            Resource::builder()
+                .with_service_name(config.service_name.clone())
+                .build(),
+        )
+        .build())
+}
+
+// dependencies' spans go through the same filters as the logs
+pub fn tracing_subscriber_for(
+    provider: &SdkTracerProvider,
+    config: &TelemetryConfig,
+    filters: &str,
+) -> impl tracing::Subscriber + Send + Sync {
+    let tracer = provider.tracer(config.service_name.clone());
+    let filter = EnvFilter::builder().parse_lossy(format!("{filters},{OWN_SPANS}"));
+
+    Registry::default()
+        .with(filter)
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_tracer_provider, tracing_subscriber_for};
+    use shared::config::TelemetryConfig;
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::mpsc,
+        thread,
+        time::Duration,
+    };
+
+    // minimal otlp/http collector: accepts one request and hands back its path and body
+    fn collector_stub() -> (String, mpsc::Receiver<(String, Vec<u8>)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}/v1/traces", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':')
+                    && name.eq_ignore_ascii_case("content-length")
+                {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+
+            let mut stream = stream;
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .unwrap();
+            let path = request_line.split(' ').nth(1).unwrap_or("").to_string();
+            tx.send((path, body)).unwrap();
+        });
+
+        (endpoint, rx)
+    }
+
+    #[test]
+    fn test_export_spans_to_collector() {
+        let (endpoint, rx) = collector_stub();
+        let config = TelemetryConfig {
+            enabled: true,
+            otlp_endpoint: endpoint,
+            ..TelemetryConfig::default()
+        };
+        let provider = build_tracer_provider(&config).unwrap();
+
+        let subscriber = tracing_subscriber_for(&provider, &config, "info,hyper=warn");
+        tracing::subscriber::with_default(subscriber, || {
+            let _span = tracing::debug_span!(target: "infra::db::queries", "db.query").entered();
+            let _noisy = tracing::debug_span!(target: "h2::codec", "h2.frame").entered();
+            let _filtered = tracing::info_span!(target: "hyper::client", "hyper.conn").entered();
+        });
+        provider.force_flush().unwrap();
+
+        let (path, body) = rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(path, "/v1/traces");
+        assert!(body.windows(8).any(|w| w == b"db.query"));
+        assert!(body.windows(11).any(|w| w == b"simple-chat"));
+        // dependencies' spans follow the log filters
+        assert!(!body.windows(8).any(|w| w == b"h2.frame"));
+        assert!(!body.windows(10).any(|w| w == b"hyper.conn"));
+
+        provider.shutdown().unwrap();
+    }
+}
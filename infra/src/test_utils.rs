@@ -1,5 +1,6 @@
-use crate::db::create_pool;
+use crate::db::{create_pool, migrations::run_migrations};
 use dotenvy::dotenv;
+use shared::config::DatabaseConfig;
 use sqlx::PgPool;
 use std::sync::Arc;
 use tokio::sync::OnceCell;
@@ -10,8 +11,18 @@ pub async fn get_db_test_pool() -> Arc<PgPool> {
     DB_TEST_POOL
         .get_or_init(|| async {
             dotenv().ok();
-            Arc::new(create_pool().await.unwrap())
+            let pool = create_pool(&test_database_config()).await.unwrap();
+            run_migrations(&pool).await.unwrap();
+            Arc::new(pool)
         })
         .await
         .clone()
 }
+
+pub fn test_database_config() -> DatabaseConfig {
+    DatabaseConfig {
+        min_connections: 0,
+        max_connections: 1,
+        ..DatabaseConfig::default()
+    }
+}
@@ -0,0 +1,9 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS room_read (
+    id INTEGER PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    username TEXT NOT NULL,
+    room_id INTEGER NOT NULL REFERENCES room(id) ON DELETE CASCADE,
+    message_id INTEGER NOT NULL,
+    updated_at TIMESTAMP DEFAULT (NOW() AT TIME ZONE 'utc'),
+    UNIQUE (username, room_id)
+);
@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE message ADD seq BIGINT;
+-- a channel rebuilt from a stale seq fails here instead of reusing seqs
+ALTER TABLE message ADD CONSTRAINT message_room_id_seq_key UNIQUE (room_id, seq);
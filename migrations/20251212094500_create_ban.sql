@@ -0,0 +1,9 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS ban (
+    id INTEGER PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    kind TEXT NOT NULL CHECK (kind IN ('user', 'ip')),
+    value TEXT NOT NULL,
+    reason TEXT,
+    created_at TIMESTAMP DEFAULT (NOW() AT TIME ZONE 'utc'),
+    UNIQUE (kind, value)
+);
@@ -0,0 +1,19 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS room_role (
+    id INTEGER PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    room_id INTEGER NOT NULL REFERENCES room(id) ON DELETE CASCADE,
+    username TEXT NOT NULL,
+    role TEXT NOT NULL CHECK (role IN ('owner', 'moderator')),
+    created_at TIMESTAMP DEFAULT (NOW() AT TIME ZONE 'utc'),
+    UNIQUE (room_id, username)
+);
+
+CREATE TABLE IF NOT EXISTS room_ban (
+    id INTEGER PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    room_id INTEGER NOT NULL REFERENCES room(id) ON DELETE CASCADE,
+    username TEXT NOT NULL,
+    reason TEXT,
+    banned_by TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT (NOW() AT TIME ZONE 'utc'),
+    UNIQUE (room_id, username)
+);
@@ -0,0 +1,37 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS webhook_subscription (
+    id SERIAL PRIMARY KEY,
+    url TEXT NOT NULL,
+    secret TEXT NOT NULL,
+    -- null subscribes to every room; no foreign key, rooms only reach postgres once someone joins
+    room_uuid UUID,
+    -- empty subscribes to every event
+    events TEXT[] NOT NULL DEFAULT '{}',
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'utc')
+);
+CREATE INDEX IF NOT EXISTS webhook_subscription_room_uuid_idx ON webhook_subscription (room_uuid);
+
+CREATE TABLE IF NOT EXISTS webhook_delivery (
+    id BIGINT PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    subscription_id INTEGER NOT NULL REFERENCES webhook_subscription(id) ON DELETE CASCADE,
+    event TEXT NOT NULL,
+    -- kept as text so the signed body is byte for byte what was queued
+    payload TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    next_attempt_at TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'utc'),
+    created_at TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'utc')
+);
+CREATE INDEX IF NOT EXISTS webhook_delivery_next_attempt_at_idx ON webhook_delivery (next_attempt_at);
+
+CREATE TABLE IF NOT EXISTS webhook_dead_letter (
+    id BIGINT PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    subscription_id INTEGER NOT NULL REFERENCES webhook_subscription(id) ON DELETE CASCADE,
+    event TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    attempts INTEGER NOT NULL,
+    last_error TEXT,
+    created_at TIMESTAMP NOT NULL,
+    failed_at TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'utc')
+);
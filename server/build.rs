@@ -0,0 +1,54 @@
+use std::{env, path::Path, process::Command};
+
+fn main() {
+    let git_commit = command_output("git", &["rev-parse", "HEAD"]);
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = command_output(&rustc, &["--version"]);
+
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(name, _)| {
+            name.strip_prefix("CARGO_FEATURE_")
+                .map(|f| f.to_lowercase().replace('_', "-"))
+        })
+        .collect();
+    features.sort_unstable();
+
+    println!("cargo:rustc-env=BUILD_GIT_COMMIT={}", git_commit);
+    println!("cargo:rustc-env=BUILD_RUSTC_VERSION={}", rustc_version);
+    println!("cargo:rustc-env=BUILD_FEATURES={}", features.join(","));
+    println!(
+        "cargo:rustc-env=BUILD_TIMESTAMP={}",
+        chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+    );
+
+    // only a new commit changes the metadata; rerunning on source edits would also
+    // change BUILD_TIMESTAMP and rebuild the crate every time
+    let head = command_output("git", &["rev-parse", "--git-path", "HEAD"]);
+    if head == "unknown" {
+        println!("cargo:rerun-if-changed=build.rs");
+        return;
+    }
+    println!("cargo:rerun-if-changed={}", head);
+    let head_ref = command_output("git", &["symbolic-ref", "-q", "HEAD"]);
+    if head_ref != "unknown" {
+        let ref_path = command_output("git", &["rev-parse", "--git-path", &head_ref]);
+        // a packed ref has no file of its own
+        if Path::new(&ref_path).exists() {
+            println!("cargo:rerun-if-changed={}", ref_path);
+        } else {
+            let packed_refs = command_output("git", &["rev-parse", "--git-path", "packed-refs"]);
+            println!("cargo:rerun-if-changed={}", packed_refs);
+        }
+    }
+}
+
+fn command_output(program: &str, args: &[&str]) -> String {
+    Command::new(program)
+        .args(args)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|s| s.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
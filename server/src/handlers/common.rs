@@ -1,32 +1,153 @@
-use axum::{http::StatusCode, response::IntoResponse};
+use std::{fmt::Display, future::Future, time::Duration};
 
-use crate::models::ApiResponse;
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use infra::{
+    cache::ping_redis,
+    db::{applied_migration_version, ping_database},
+};
+use shared::models::AppState;
+use tokio::time::{self, Instant};
 
-pub async fn handle_version() -> impl IntoResponse {
-    let version = env!("CARGO_PKG_VERSION");
-    ApiResponse::build(true, version, StatusCode::OK)
+use crate::models::{ApiResponse, DependencyStatus, ReadinessResponse, VersionResponse};
+
+// migrations only run before the server starts, so the router reads this once
+pub async fn read_migration_version(app_state: &AppState) -> Option<i64> {
+    applied_migration_version(&app_state.db_pool)
+        .await
+        .unwrap_or_else(|e| {
+            log::error!("failed to read applied migration version: {e}");
+            None
+        })
+}
+
+pub async fn handle_version(migration_version: Option<i64>) -> impl IntoResponse {
+    ApiResponse::build(
+        true,
+        VersionResponse::new(migration_version),
+        StatusCode::OK,
+    )
 }
 
 pub async fn handle_health() -> StatusCode {
     StatusCode::OK
 }
 
+pub async fn handle_ready(State(app_state): State<AppState>) -> impl IntoResponse {
+    let timeout = Duration::from_millis(app_state.config.server.readiness_timeout_ms);
+
+    let (database, redis) = tokio::join!(
+        check_dependency("database", timeout, ping_database(&app_state.db_pool)),
+        check_dependency("redis", timeout, ping_redis(&app_state.redis_client)),
+    );
+    if !redis.healthy {
+        app_state.metrics.record_redis_error();
+    }
+
+    let readiness = ReadinessResponse::new(database, redis);
+    let status_code = if readiness.is_ready() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    ApiResponse::build(readiness.is_ready(), readiness, status_code)
+}
+
+// the endpoint is public, so driver errors only go to the log
+async fn check_dependency<E: Display>(
+    name: &str,
+    timeout: Duration,
+    ping: impl Future<Output = Result<(), E>>,
+) -> DependencyStatus {
+    let started = Instant::now();
+    let result = time::timeout(timeout, ping).await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+
+    match result {
+        Ok(Ok(())) => DependencyStatus::new(true, latency_ms, None),
+        Ok(Err(e)) => {
+            log::error!("{name} readiness check failed: {e}");
+            DependencyStatus::new(false, latency_ms, Some("unavailable".to_string()))
+        }
+        Err(_) => {
+            log::error!("{name} readiness check timed out");
+            DependencyStatus::new(false, latency_ms, Some("timed out".to_string()))
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::test_utils::get_test_server;
+    use crate::{
+        handlers::init_app,
+        test_utils::{get_test_app_state, get_test_server},
+    };
+    use axum_test::TestServer;
+    use serde_json::Value;
+    use std::sync::Arc;
 
     #[tokio::test]
     async fn test_handle_version() {
         let server = get_test_server().await;
 
         let response = server.get("/version").await;
-        assert_eq!(response.status_code(), 200)
+        assert_eq!(response.status_code(), 200);
+
+        let data = response.json::<Value>()["data"].clone();
+        assert_eq!(data["version"], env!("CARGO_PKG_VERSION"));
+        assert!(data["rustc_version"].as_str().unwrap().starts_with("rustc"));
+        assert!(data["features"].is_array());
+        assert!(data["migration_version"].as_i64().is_some());
+    }
+    #[tokio::test]
+    async fn test_handle_version_reads_migration_once() {
+        let app_state = get_test_app_state().await;
+        let server = TestServer::new(init_app(app_state.clone()).await).unwrap();
+        app_state.db_pool.close().await;
+
+        let response = server.get("/version").await;
+        assert_eq!(response.status_code(), 200);
+        assert!(
+            response.json::<Value>()["data"]["migration_version"]
+                .as_i64()
+                .is_some()
+        );
     }
     #[tokio::test]
     async fn test_handle_health() {
         let server = get_test_server().await;
 
         let response = server.get("/health").await;
+        assert_eq!(response.status_code(), 200);
+
+        let response = server.get("/health/live").await;
         assert_eq!(response.status_code(), 200)
     }
+    #[tokio::test]
+    async fn test_handle_ready() {
+        let server = get_test_server().await;
+
+        let response = server.get("/health/ready").await;
+        assert_eq!(response.status_code(), 200);
+
+        let data = response.json::<Value>()["data"].clone();
+        assert_eq!(data["status"], "ready");
+        assert_eq!(data["database"]["healthy"], true);
+        assert_eq!(data["redis"]["healthy"], true);
+    }
+    #[tokio::test]
+    async fn test_handle_ready_return_503() {
+        let mut app_state = get_test_app_state().await;
+        app_state.redis_client = Arc::new(redis::Client::open("redis://127.0.0.1:1").unwrap());
+        let server = TestServer::new(init_app(app_state).await).unwrap();
+
+        let response = server.get("/health/ready").await;
+        assert_eq!(response.status_code(), 503);
+
+        let data = response.json::<Value>()["data"].clone();
+        assert_eq!(data["status"], "degraded");
+        assert_eq!(data["database"]["healthy"], true);
+        assert_eq!(data["redis"]["healthy"], false);
+        assert_eq!(data["redis"]["error"], "unavailable");
+    }
 }
@@ -0,0 +1,133 @@
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::header::CONTENT_TYPE,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use infra::logging::correlation_id;
+use shared::{metrics::write_metric, models::AppState};
+use tokio::time::Instant;
+use tracing::{Instrument, debug_span, field::Empty};
+
+pub async fn handle_metrics(State(app_state): State<AppState>) -> impl IntoResponse {
+    let rooms = app_state.channels.lock().await.len() as u64;
+    let pool = &app_state.db_pool;
+
+    let mut body = String::new();
+    write_metric(
+        &mut body,
+        "chat_rooms_active",
+        "Rooms with at least one open websocket",
+        "gauge",
+        rooms,
+    );
+    write_metric(
+        &mut body,
+        "chat_db_pool_connections",
+        "Connections currently held by the database pool",
+        "gauge",
+        pool.size() as u64,
+    );
+    write_metric(
+        &mut body,
+        "chat_db_pool_idle_connections",
+        "Idle connections in the database pool",
+        "gauge",
+        pool.num_idle() as u64,
+    );
+    write_metric(
+        &mut body,
+        "chat_db_pool_max_connections",
+        "Configured maximum size of the database pool",
+        "gauge",
+        pool.options().get_max_connections() as u64,
+    );
+    body.push_str(&app_state.metrics.render());
+
+    ([(CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+}
+
+pub async fn observe_request(
+    State(app_state): State<AppState>,
+    matched_path: Option<MatchedPath>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let started = Instant::now();
+    let method = request.method().to_string();
+    let route = matched_path
+        .as_ref()
+        .map(MatchedPath::as_str)
+        .unwrap_or("unmatched")
+        .to_string();
+    let span = debug_span!(
+        "http.request",
+        http.request.method = %method,
+        http.route = %route,
+        http.response.status_code = Empty,
+        request_id = correlation_id().unwrap_or_default(),
+    );
+
+    let response = next.run(request).instrument(span.clone()).await;
+
+    span.record("http.response.status_code", response.status().as_u16());
+    app_state
+        .metrics
+        .record_http_request(&method, &route, started.elapsed());
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum_test::TestServer;
+    use shared::config::{AdminConfig, Config};
+
+    use crate::{
+        handlers::init_app,
+        test_utils::{get_test_app_state, get_test_server},
+    };
+
+    const TOKEN: &str = "test-admin-token-0123";
+
+    #[tokio::test]
+    async fn test_handle_metrics() {
+        let mut app_state = get_test_app_state().await;
+        app_state.config = Arc::new(Config {
+            admin: AdminConfig {
+                token: Some(TOKEN.to_string()),
+            },
+            ..Config::default()
+        });
+        let server = TestServer::new(init_app(app_state).await).unwrap();
+
+        server
+            .get("/room/list")
+            .add_header("x-forwarded-for", "127.0.0.29")
+            .await;
+        let response = server.get("/metrics").await;
+        assert_eq!(response.status_code(), 401);
+
+        let response = server
+            .get("/metrics")
+            .add_header("authorization", format!("Bearer {}", TOKEN))
+            .await;
+        assert_eq!(response.status_code(), 200);
+
+        let body = response.text();
+        assert!(body.contains("chat_rooms_active 0\n"));
+        assert!(body.contains("chat_db_pool_max_connections 2\n"));
+        assert!(body.contains(
+            "chat_http_request_duration_seconds_count{method=\"GET\",route=\"/room/list\"} 1\n"
+        ));
+    }
+    #[tokio::test]
+    async fn test_handle_metrics_disabled_without_admin_token() {
+        let server = get_test_server().await;
+
+        let response = server.get("/metrics").await;
+        assert_eq!(response.status_code(), 404);
+    }
+}
@@ -1,28 +1,55 @@
 use axum::{
     Router,
     http::Method,
+    middleware,
     routing::{any, get, post},
 };
 use axum_helmet::{Helmet, HelmetLayer};
 use shared::models::AppState;
 use tower_http::cors::{Any, CorsLayer};
 
-use crate::handlers::{
-    common::{handle_health, handle_version},
-    room::{handle_connect_room, handle_create_room, handle_rooms_list},
+use crate::{
+    handlers::{
+        admin::{admin_router, require_admin_token},
+        common::{handle_health, handle_ready, handle_version, read_migration_version},
+        metrics::{handle_metrics, observe_request},
+        room::{
+            handle_connect_room, handle_create_room, handle_room_members, handle_rooms_list,
+            handle_unread_counts,
+        },
+    },
+    request_id::assign_request_id,
 };
 
+mod admin;
 mod common;
+mod metrics;
 mod room;
 
 pub async fn init_app(app_state: AppState) -> Router {
     let cors = CorsLayer::new()
         .allow_methods([Method::GET, Method::POST])
         .allow_origin(Any);
+    let migration_version = read_migration_version(&app_state).await;
 
     Router::new()
-        .route("/version", get(handle_version))
+        .route("/version", get(move || handle_version(migration_version)))
         .route("/health", get(handle_health))
+        .route("/health/live", get(handle_health))
+        .route(
+            "/health/ready",
+            get(handle_ready).with_state(app_state.clone()),
+        )
+        // per-room and per-policy counters are for operators only
+        .route(
+            "/metrics",
+            get(handle_metrics)
+                .route_layer(middleware::from_fn_with_state(
+                    app_state.clone(),
+                    require_admin_token,
+                ))
+                .with_state(app_state.clone()),
+        )
         .route(
             "/room/create",
             post(handle_create_room).with_state(app_state.clone()),
@@ -31,7 +58,20 @@ pub async fn init_app(app_state: AppState) -> Router {
             "/room/{uuid}",
             any(handle_connect_room).with_state(app_state.clone()),
         )
-        .route("/room/list", get(handle_rooms_list).with_state(app_state))
+        .route(
+            "/room/{uuid}/members",
+            get(handle_room_members).with_state(app_state.clone()),
+        )
+        .route(
+            "/room/unread",
+            get(handle_unread_counts).with_state(app_state.clone()),
+        )
+        .route(
+            "/room/list",
+            get(handle_rooms_list).with_state(app_state.clone()),
+        )
+        .nest("/admin", admin_router(app_state.clone()))
+        .route_layer(middleware::from_fn_with_state(app_state, observe_request))
         .layer(HelmetLayer::new(
             Helmet::new()
                 .add(helmet_core::XContentTypeOptions::nosniff())
@@ -47,4 +87,5 @@ pub async fn init_app(app_state: AppState) -> Router {
                 .add(helmet_core::CrossOriginOpenerPolicy::same_origin()),
         ))
         .layer(cors)
+        .layer(middleware::from_fn(assign_request_id))
 }
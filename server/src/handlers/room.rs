@@ -1,10 +1,16 @@
-use std::{sync::Arc};
+use std::{
+    cmp::Reverse,
+    collections::hash_map::Entry,
+    sync::{Arc, atomic::Ordering},
+    time::Duration,
+};
 
 use axum::{
     Json,
+    body::Bytes,
     extract::{
-        Path, State, WebSocketUpgrade,
-        ws::{Message as WsMessage, WebSocket},
+        Path, Query, State, WebSocketUpgrade,
+        ws::{CloseFrame, Message as WsMessage, WebSocket, close_code},
     },
     http::{HeaderMap, StatusCode},
     response::{IntoResponse, Response},
@@ -17,25 +23,56 @@ use futures_util::{
 use rand::distr::{Alphanumeric, SampleString};
 use redis::AsyncTypedCommands;
 use serde_json::{Value, json};
-use shared::{helpers::generate_uuid_v4, models::AppState, types::DefaultError};
-use tokio::sync::broadcast::{self, Sender};
+use shared::{
+    helpers::generate_uuid_v4,
+    models::{AppState, RoomChannel, RoomSender},
+    types::DefaultError,
+};
+use tokio::{
+    sync::{
+        Notify,
+        broadcast::{Receiver, error::RecvError},
+        mpsc,
+    },
+    time::{self, Instant},
+};
+use tracing::{Instrument, debug_span};
 use uuid::Uuid;
 
 use crate::{
-    models::{ApiResponse, RoomResponse},
-    rate_limiter::RateLimiter,
+    models::{
+        ApiResponse, ClientFrame, ConnectRoomQuery, FrameError, RoomResponse, UnreadQuery,
+        UnreadResponse,
+    },
+    rate_limiter::{RateLimiter, Subject},
+    utils::{extract_request_ip, public_base_url, record_audit},
+    webhooks,
+};
+use infra::{
+    db::{
+        audit::AuditEntry,
+        models::{Ban, Message, Role, Room, RoomBan, RoomRead, RoomRole},
+        webhooks::WebhookDelivery,
+    },
+    logging::{correlation_id, with_correlation_id},
 };
-use infra::db::models::{Message, Room};
 
-pub async fn handle_create_room(
-    State(app_state): State<AppState>,
-    headers: HeaderMap,
-) -> impl IntoResponse {
-    if !RateLimiter::run(&headers, 10, 600, Arc::clone(&app_state.redis_client)).await {
-        return ApiResponse::build(false, String::new(), StatusCode::TOO_MANY_REQUESTS);
+pub async fn handle_create_room(State(app_state): State<AppState>, headers: HeaderMap) -> Response {
+    let policy = &app_state.config.rate_limit.create_room;
+    if !RateLimiter::run(
+        &Subject::from_headers(&headers),
+        "create_room",
+        policy,
+        &app_state,
+    )
+    .await
+    {
+        return ApiResponse::build(false, String::new(), StatusCode::TOO_MANY_REQUESTS)
+            .into_response();
     }
 
-    let room_uuid = generate_uuid_v4().to_string();
+    let uuid = generate_uuid_v4();
+    let room_uuid = uuid.to_string();
     let mut conn = match app_state
         .redis_client
         .get_multiplexed_async_connection()
@@ -44,27 +81,44 @@ pub async fn handle_create_room(
         Ok(c) => c,
         Err(e) => {
             log::error!("failed to open redis connection: {e}");
+            app_state.metrics.record_redis_error();
             return ApiResponse::build(
                 false,
-                "failed to create room".into(),
+                "failed to create room".to_string(),
                 StatusCode::INTERNAL_SERVER_ERROR,
-            );
+            )
+            .into_response();
         }
     };
     let _ = conn
         .set_ex(
             format!("room:{}", room_uuid).as_str(),
             room_uuid.clone(),
-            3600,
+            app_state.config.room.ttl_secs,
         )
         .await;
-    ApiResponse::build(true, room_uuid, StatusCode::OK)
+    webhooks::notify(
+        &app_state,
+        "room.created",
+        uuid,
+        json!({ "uuid": room_uuid }),
+    )
+    .await;
+
+    let connect_url = room_connect_url(&app_state, &headers, &room_uuid);
+    ApiResponse::build_with(
+        true,
+        room_uuid,
+        json!({ "connect_url": connect_url }),
+        StatusCode::OK,
+    )
+    .into_response()
 }
 
-// TODO: write some test for this endpoint
 pub async fn handle_connect_room(
     ws: WebSocketUpgrade,
     Path(uuid): Path<String>,
+    Query(query): Query<ConnectRoomQuery>,
     State(app_state): State<AppState>,
     headers: HeaderMap,
 ) -> Response {
@@ -87,6 +141,7 @@ pub async fn handle_connect_room(
         Ok(v) => v,
         Err(e) => {
             log::error!("failed to open redis connection: {e}");
+            app_state.metrics.record_redis_error();
             return standard_err_code;
         }
     };
@@ -94,6 +149,7 @@ pub async fn handle_connect_room(
         Ok(v) => v,
         Err(e) => {
             log::error!("failed to check room cache exists: {e}");
+            app_state.metrics.record_redis_error();
             return standard_err_code;
         }
     };
@@ -131,15 +187,93 @@ pub async fn handle_connect_room(
         }
     }
 
+    let resumed_username = match &query.resume {
+        Some(token) => conn.get(format!("resume:{}", token)).await.unwrap_or(None),
+        None => None,
+    };
+    let session = match resumed_username {
+        Some(username) => (username, query.resume.unwrap_or_default()),
+        None => (
+            format!(
+                "anonymous_{}",
+                Alphanumeric.sample_string(&mut rand::rng(), 10)
+            ),
+            Alphanumeric.sample_string(&mut rand::rng(), 32),
+        ),
+    };
+    match Ban::is_banned(
+        None,
+        Some(Arc::clone(&app_state.db_pool)),
+        session.0.clone(),
+        extract_request_ip(&headers),
+    )
+    .await
+    {
+        Ok(true) => return StatusCode::FORBIDDEN.into_response(),
+        Ok(false) => (),
+        Err(e) => log::error!("failed to check bans: {e}"),
+    }
+    match RoomBan::is_banned(
+        None,
+        Some(Arc::clone(&app_state.db_pool)),
+        room_id,
+        session.0.clone(),
+    )
+    .await
+    {
+        Ok(true) => return StatusCode::FORBIDDEN.into_response(),
+        Ok(false) => (),
+        Err(e) => log::error!("failed to check room bans: {e}"),
+    }
+    // whoever opens a freshly created room owns it
+    if cache_exits && let Err(e) = set_owner(&app_state, room_id, session.0.clone()).await {
+        log::error!("failed to set room owner: {e}");
+    }
+    if let Err(e) = conn
+        .set_ex(
+            format!("resume:{}", session.1),
+            session.0.clone(),
+            app_state.config.room.resume_ttl_secs,
+        )
+        .await
+    {
+        log::error!("failed to store resume token: {e}");
+        app_state.metrics.record_redis_error();
+    }
+
+    // the upgrade request id follows the connection for its whole lifetime
+    let connection_id = correlation_id().unwrap_or_else(|| generate_uuid_v4().to_string());
+    let span = debug_span!(
+        "ws.connection",
+        room = %parsed_uuid,
+        connection_id = %connection_id
+    );
+    let ws_config = &app_state.config.ws;
+    let ws = ws
+        .max_frame_size(ws_config.max_frame_bytes)
+        .max_message_size(ws_config.max_message_bytes);
     ws.on_upgrade(move |socket| {
-        ConnectRoomWebSocket::join(socket, (parsed_uuid, room_id), app_state, headers)
+        with_correlation_id(
+            connection_id,
+            ConnectRoomWebSocket::join(
+                socket,
+                (parsed_uuid, room_id),
+                session,
+                query.since,
+                app_state,
+                headers,
+            )
+            .instrument(span),
+        )
     })
 }
 
 struct ConnectRoomWebSocket {
+    connection_id: String,
     username: String,
+    resume_token: String,
     room_info: (Uuid, i32),
-    channel_tx: Sender<Json<Value>>,
+    channel_tx: RoomSender,
     app_state: AppState,
 }
 
@@ -147,23 +281,52 @@ impl ConnectRoomWebSocket {
     async fn join(
         socket: WebSocket,
         room_info: (Uuid, i32),
+        session: (String, String),
+        since: Option<i64>,
         app_state: AppState,
         headers: HeaderMap,
     ) {
         let (mut socket_send, socket_recv) = socket.split();
-        let username = format!(
-            "anonymous_{}",
-            Alphanumeric.sample_string(&mut rand::rng(), 10)
-        );
+        let (username, resume_token) = session;
+
         let channel_tx = {
             let mut map = app_state.channels.lock().await;
-            map.entry(room_info.0)
-                .or_insert_with(|| broadcast::channel(100).0)
-                .clone()
+            // seeded under the same lock, a channel rebuilt from a stale seq would reuse seqs
+            let room_channel = match map.entry(room_info.0) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let latest = Message::read_latest(
+                        None,
+                        Some(Arc::clone(&app_state.db_pool)),
+                        room_info.1,
+                    )
+                    .await;
+                    let last_seq = match latest {
+                        Ok(message) => message.and_then(|m| m.get_seq()).unwrap_or(0),
+                        Err(e) => {
+                            log::error!("failed to read the last seq of room {}: {e}", room_info.0);
+                            return;
+                        }
+                    };
+                    entry.insert(RoomChannel::new(
+                        app_state.config.ws.broadcast_capacity,
+                        last_seq,
+                    ))
+                }
+            };
+            room_channel.members.push(username.clone());
+            room_channel.tx.clone()
         };
 
+        app_state
+            .metrics
+            .active_sockets
+            .fetch_add(1, Ordering::Relaxed);
+        log::info!("{} joined room {}", username, room_info.0);
         let mut connect_room_web_socket = Self {
+            connection_id: correlation_id().unwrap_or_default(),
             username,
+            resume_token,
             room_info,
             channel_tx,
             app_state,
@@ -172,15 +335,18 @@ impl ConnectRoomWebSocket {
             .send_info(&mut socket_send)
             .await
             .unwrap_or(());
-        connect_room_web_socket
-            .load_previous_messages(&mut socket_send)
+        // subscribed before reading history, the overlap is dropped by seq
+        let channel_rx = connect_room_web_socket.channel_tx.subscribe();
+        let last_seq = connect_room_web_socket
+            .load_previous_messages(&mut socket_send, since)
             .await
-            .unwrap_or(());
+            .unwrap_or(since.unwrap_or(0));
         connect_room_web_socket
-            .process(socket_send, socket_recv, headers)
+            .process(socket_send, socket_recv, channel_rx, last_seq, headers)
             .await;
     }
 
+    #[tracing::instrument(level = "debug", skip_all)]
     async fn send_info(
         &mut self,
         socket_send: &mut SplitSink<WebSocket, WsMessage>,
@@ -188,171 +354,1091 @@ impl ConnectRoomWebSocket {
         socket_send
             .send(WsMessage::Text("Connected".into()))
             .await?;
-        self.channel_tx.send(Json(Value::String(format!(
-            "user {} joined to room",
-            self.username
-        ))))?;
+        socket_send
+            .send(WsMessage::text(
+                json!({
+                    "type": "session",
+                    "user": self.username,
+                    "resume_token": self.resume_token,
+                })
+                .to_string(),
+            ))
+            .await?;
+
+        let members = self
+            .app_state
+            .channels
+            .lock()
+            .await
+            .get(&self.room_info.0)
+            .map(|room_channel| room_channel.members.clone())
+            .unwrap_or_default();
+        socket_send
+            .send(WsMessage::text(
+                json!({ "type": "members", "members": members }).to_string(),
+            ))
+            .await?;
+        self.channel_tx.send(json!({
+            "type": "join",
+            "user": self.username,
+            "message": format!("user {} joined to room", self.username),
+        }))?;
+        webhooks::notify(
+            &self.app_state,
+            "member.joined",
+            self.room_info.0,
+            json!({ "user": self.username }),
+        )
+        .await;
 
         Ok(())
     }
 
+    #[tracing::instrument(level = "debug", skip_all)]
     async fn load_previous_messages(
         &mut self,
         socket_send: &mut SplitSink<WebSocket, WsMessage>,
-    ) -> Result<(), DefaultError> {
-        for message in Message::read(
-            None,
-            Some(Arc::clone(&self.app_state.db_pool)),
-            self.room_info.1,
-            200,
-        )
-        .await
-        .unwrap_or(Vec::new())
-        {
+        since: Option<i64>,
+    ) -> Result<i64, DefaultError> {
+        let db_pool = Some(Arc::clone(&self.app_state.db_pool));
+        let limit = self.app_state.config.room.history_limit;
+        let Some(since) = since else {
+            let messages = Message::read(None, db_pool, self.room_info.1, limit).await;
+            let mut last_seq = 0;
+            for message in messages.unwrap_or(Vec::new()) {
+                last_seq = last_seq.max(message.get_seq().unwrap_or(0));
+                socket_send
+                    .send(WsMessage::text(message_frame(&message).to_string()))
+                    .await?
+            }
+            return Ok(last_seq);
+        };
+
+        // one extra row tells whether the gap is larger than history_limit
+        let mut messages = Message::read_since(None, db_pool, self.room_info.1, since, limit + 1)
+            .await
+            .unwrap_or(Vec::new());
+        let has_more = messages.len() > limit as usize;
+        messages.truncate(limit as usize);
+
+        let mut next_since = since;
+        for message in messages {
+            next_since = next_since.max(message.get_seq().unwrap_or(since));
             socket_send
-                .send(WsMessage::Text(message.get_message().into()))
+                .send(WsMessage::text(message_frame(&message).to_string()))
                 .await?
         }
+        // a client with more to catch up reconnects with ?since=next_since
+        socket_send
+            .send(WsMessage::text(
+                json!({ "type": "history", "has_more": has_more, "next_since": next_since })
+                    .to_string(),
+            ))
+            .await?;
+        Ok(next_since)
+    }
+
+    async fn mark_read(
+        app_state: &AppState,
+        channel_tx: &RoomSender,
+        username: &str,
+        room_id: i32,
+        message_id: i32,
+    ) -> Result<(), DefaultError> {
+        let mut db_tx = app_state.db_pool.begin().await?;
+        let Some(room_read) =
+            RoomRead::upsert(&mut db_tx, username.to_string(), room_id, message_id).await?
+        else {
+            // any client can name a message from another room, not worth more than debug
+            log::debug!("ignored read of message {message_id} outside room {room_id}");
+            return Ok(());
+        };
+        db_tx.commit().await?;
+
+        channel_tx.send(json!({
+            "type": "read",
+            "user": username,
+            "message_id": room_read.get_message_id(),
+        }))?;
+
+        Ok(())
+    }
+
+    async fn moderate(
+        app_state: &AppState,
+        channel_tx: &RoomSender,
+        actor: &str,
+        room_info: (Uuid, i32),
+        frame: ClientFrame,
+    ) -> Result<(), String> {
+        let target = match &frame {
+            ClientFrame::Kick { user }
+            | ClientFrame::Mute { user, .. }
+            | ClientFrame::Ban { user, .. }
+            | ClientFrame::SetRole { user, .. } => user.clone(),
+            ClientFrame::Read { .. } => return Ok(()),
+        };
+        let internal_error = |e: DefaultError| {
+            log::error!("failed to apply moderation: {e}");
+            "failed to apply moderation".to_string()
+        };
+
+        let db_pool = Some(Arc::clone(&app_state.db_pool));
+        let actor_role = RoomRole::role_of(None, db_pool.clone(), room_info.1, actor.to_string())
+            .await
+            .map_err(internal_error)?;
+        let target_role = RoomRole::role_of(None, db_pool, room_info.1, target.clone())
+            .await
+            .map_err(internal_error)?;
+
+        let required = match frame {
+            ClientFrame::SetRole { .. } => Role::Owner,
+            _ => Role::Moderator,
+        };
+        if actor_role.rank() < required.rank() {
+            return Err(format!("only a room {} can do that", required.as_str()));
+        }
+        if target == actor {
+            return Err("you cannot moderate yourself".to_string());
+        }
+        if target_role.rank() >= actor_role.rank() {
+            return Err(format!("{} is a room {}", target, target_role.as_str()));
+        }
+
+        let event = match frame {
+            ClientFrame::Kick { user } => {
+                let present = app_state
+                    .channels
+                    .lock()
+                    .await
+                    .get(&room_info.0)
+                    .is_some_and(|room_channel| room_channel.members.contains(&user));
+                if !present {
+                    return Err(format!("{} is not in this room", user));
+                }
+                record_audit(
+                    &app_state.db_pool,
+                    AuditEntry::new(actor, "room.kick")
+                        .room(room_info.1)
+                        .target(user.clone()),
+                )
+                .await
+                .map_err(internal_error)?;
+
+                json!({
+                    "type": "system",
+                    "event": "kicked",
+                    "user": user,
+                    "by": actor,
+                    "message": format!("user {} was kicked by {}", user, actor),
+                })
+            }
+            ClientFrame::Mute { user, duration } => {
+                let max_mute_secs = app_state.config.room.max_mute_secs;
+                if duration > max_mute_secs {
+                    return Err(format!("mutes are limited to {} seconds", max_mute_secs));
+                }
+
+                Self::mute(app_state, room_info, &user, actor, duration, None)
+                    .await
+                    .map_err(internal_error)?
+            }
+            ClientFrame::Ban { user, reason } => {
+                let mut db_tx = app_state
+                    .db_pool
+                    .begin()
+                    .await
+                    .map_err(|e| internal_error(e.into()))?;
+                RoomBan::create(
+                    &mut db_tx,
+                    room_info.1,
+                    user.clone(),
+                    reason.clone(),
+                    actor.to_string(),
+                )
+                .await
+                .map_err(internal_error)?;
+                db_tx.commit().await.map_err(|e| internal_error(e.into()))?;
+
+                json!({
+                    "type": "system",
+                    "event": "banned",
+                    "user": user,
+                    "by": actor,
+                    "reason": reason,
+                    "message": format!("user {} was banned by {}", user, actor),
+                })
+            }
+            ClientFrame::SetRole { user, role } => {
+                let role = match Role::parse(&role) {
+                    Some(role @ (Role::Moderator | Role::Member)) => role,
+                    _ => return Err("role must be moderator or member".to_string()),
+                };
+                let mut db_tx = app_state
+                    .db_pool
+                    .begin()
+                    .await
+                    .map_err(|e| internal_error(e.into()))?;
+                RoomRole::set(&mut db_tx, room_info.1, user.clone(), role, actor)
+                    .await
+                    .map_err(internal_error)?;
+                db_tx.commit().await.map_err(|e| internal_error(e.into()))?;
+
+                json!({
+                    "type": "system",
+                    "event": "role_changed",
+                    "user": user,
+                    "role": role.as_str(),
+                    "by": actor,
+                })
+            }
+            ClientFrame::Read { .. } => return Ok(()),
+        };
+        log::info!("{} in room {}: {}", actor, room_info.0, event["event"]);
+
+        let _ = channel_tx.send(event);
+        Ok(())
+    }
+
+    // a duration of 0 lifts the mute; returns the system event to broadcast
+    async fn mute(
+        app_state: &AppState,
+        room_info: (Uuid, i32),
+        user: &str,
+        actor: &str,
+        duration: u64,
+        reason: Option<&str>,
+    ) -> Result<Value, DefaultError> {
+        let key = mute_key(room_info.0, user);
+        let mut conn = app_state
+            .redis_client
+            .get_multiplexed_async_connection()
+            .await?;
+        let result = if duration == 0 {
+            conn.del(key).await.map(|_| ())
+        } else {
+            conn.set_ex(key, actor, duration).await
+        };
+        if let Err(e) = result {
+            app_state.metrics.record_redis_error();
+            return Err(e.into());
+        }
+
+        let entry = if duration == 0 {
+            AuditEntry::new(actor, "room.unmute")
+        } else {
+            let detail = match reason {
+                Some(reason) => format!("{} seconds: {}", duration, reason),
+                None => format!("{} seconds", duration),
+            };
+            AuditEntry::new(actor, "room.mute").detail(detail)
+        };
+        record_audit(&app_state.db_pool, entry.room(room_info.1).target(user)).await?;
+
+        if duration == 0 {
+            return Ok(json!({
+                "type": "system",
+                "event": "unmuted",
+                "user": user,
+                "by": actor,
+                "message": format!("user {} was unmuted by {}", user, actor),
+            }));
+        }
+        let mut event = json!({
+            "type": "system",
+            "event": "muted",
+            "user": user,
+            "by": actor,
+            "duration": duration,
+            "message": format!("user {} was muted by {} for {} seconds", user, actor, duration),
+        });
+        if let Some(reason) = reason {
+            event["reason"] = reason.into();
+        }
+
+        Ok(event)
+    }
+    // drops the message and, when configured, mutes the sender in the room
+    async fn handle_spam(
+        app_state: &AppState,
+        channel_tx: &RoomSender,
+        room_info: (Uuid, i32),
+        username: &str,
+        reason: &str,
+    ) -> Result<(), DefaultError> {
+        log::info!("spam from {} in room {}: {}", username, room_info.0, reason);
+
+        let duration = app_state.config.spam.auto_mute_secs;
+        if duration == 0 {
+            let entry = AuditEntry::new(SPAM_ACTOR, "spam.detected")
+                .room(room_info.1)
+                .target(username)
+                .detail(reason);
+            return record_audit(&app_state.db_pool, entry).await;
+        }
+
+        let event = Self::mute(
+            app_state,
+            room_info,
+            username,
+            SPAM_ACTOR,
+            duration,
+            Some(reason),
+        )
+        .await?;
+        let _ = channel_tx.send(event);
         Ok(())
     }
+    async fn is_muted(
+        app_state: &AppState,
+        room_uuid: Uuid,
+        username: &str,
+    ) -> Result<bool, DefaultError> {
+        let mut conn = app_state
+            .redis_client
+            .get_multiplexed_async_connection()
+            .await?;
+
+        Ok(conn.exists(mute_key(room_uuid, username)).await?)
+    }
+
+    async fn resync(
+        app_state: &AppState,
+        socket_send: &mut SplitSink<WebSocket, WsMessage>,
+        room_id: i32,
+        since: i64,
+        missed: u64,
+    ) -> Result<i64, DefaultError> {
+        socket_send
+            .send(WsMessage::text(
+                json!({ "type": "lagged", "missed": missed }).to_string(),
+            ))
+            .await?;
+
+        // pages through the whole gap, the live frames after it would hide anything left out
+        let page_size = app_state.config.room.history_limit;
+        let mut last_seq = since;
+        loop {
+            let messages = Message::read_since(
+                None,
+                Some(Arc::clone(&app_state.db_pool)),
+                room_id,
+                last_seq,
+                page_size,
+            )
+            .await?;
+            let count = messages.len();
+
+            for message in messages {
+                last_seq = last_seq.max(message.get_seq().unwrap_or(last_seq));
+                socket_send
+                    .send(WsMessage::text(message_frame(&message).to_string()))
+                    .await?;
+            }
+            if count < page_size as usize {
+                break;
+            }
+        }
+
+        Ok(last_seq)
+    }
 
     async fn process(
         &mut self,
         mut socket_send: SplitSink<WebSocket, WsMessage>,
         mut socket_recv: SplitStream<WebSocket>,
+        mut channel_rx: Receiver<Json<Value>>,
+        mut last_seq: i64,
         headers: HeaderMap,
     ) {
-        let mut channel_rx = self.channel_tx.subscribe();
-
         let app_state = self.app_state.clone();
         let username = self.username.clone();
         let room_info = self.room_info;
         let channel_tx = self.channel_tx.clone();
 
-        let mut send_task = tokio::spawn(async move {
-            while let Ok(message) = channel_rx.recv().await {
-                if socket_send
-                    .send(WsMessage::text(message.to_string()))
-                    .await
-                    .is_err()
-                {
-                    break;
+        let ws_config = self.app_state.config.ws.clone();
+        let ping_period = Duration::from_secs(ws_config.ping_interval_secs);
+        let pong_timeout = Duration::from_secs(ws_config.pong_timeout_secs);
+        let idle_timeout = Duration::from_secs(ws_config.idle_timeout_secs);
+        let pong_notify = Arc::new(Notify::new());
+        let (direct_tx, mut direct_rx) = mpsc::unbounded_channel::<WsMessage>();
+
+        let send_app_state = self.app_state.clone();
+        let send_username = self.username.clone();
+        let send_pong_notify = Arc::clone(&pong_notify);
+        let mut shutdown_rx = self.app_state.shutdown.subscribe();
+        let mut send_task = tokio::spawn(with_correlation_id(
+            self.connection_id.clone(),
+            async move {
+                let mut ping_interval =
+                    time::interval_at(Instant::now() + ping_period, ping_period);
+                let pong_deadline = time::sleep(Duration::ZERO);
+                tokio::pin!(pong_deadline);
+                let mut awaiting_pong = false;
+
+                loop {
+                    let received = tokio::select! {
+                        received = channel_rx.recv() => received,
+                        direct = direct_rx.recv() => {
+                            let Some(frame) = direct else {
+                                break;
+                            };
+                            let is_close = matches!(frame, WsMessage::Close(_));
+                            if socket_send.send(frame).await.is_err() || is_close {
+                                break;
+                            }
+                            continue;
+                        }
+                        _ = ping_interval.tick() => {
+                            if socket_send.send(WsMessage::Ping(Bytes::new())).await.is_err() {
+                                break;
+                            }
+                            if !awaiting_pong {
+                                pong_deadline.as_mut().reset(Instant::now() + pong_timeout);
+                                awaiting_pong = true;
+                            }
+                            continue;
+                        }
+                        _ = send_pong_notify.notified() => {
+                            awaiting_pong = false;
+                            continue;
+                        }
+                        _ = async { shutdown_rx.wait_for(|stopping| *stopping).await.is_ok() } => {
+                            let _ = socket_send
+                                .send(WsMessage::text(
+                                    json!({
+                                        "type": "system",
+                                        "event": "restarting",
+                                        "reconnect_after_secs": ws_config.reconnect_hint_secs,
+                                    })
+                                    .to_string(),
+                                ))
+                                .await;
+                            let _ = socket_send
+                                .send(WsMessage::Close(Some(CloseFrame {
+                                    code: close_code::RESTART,
+                                    reason: "server restarting".into(),
+                                })))
+                                .await;
+                            break;
+                        }
+                        _ = &mut pong_deadline, if awaiting_pong => {
+                            log::info!("closing socket of {send_username}: pong not received in time");
+                            let _ = socket_send
+                                .send(WsMessage::Close(Some(CloseFrame {
+                                    code: close_code::AWAY,
+                                    reason: "ping timeout".into(),
+                                })))
+                                .await;
+                            break;
+                        }
+                    };
+
+                    let message = match received {
+                        Ok(v) => v,
+                        Err(RecvError::Lagged(missed)) => {
+                            send_app_state.metrics.record_lag(missed);
+                            log::warn!(
+                                "receiver of room {} lagged by {missed} messages",
+                                room_info.0
+                            );
+
+                            match Self::resync(
+                                &send_app_state,
+                                &mut socket_send,
+                                room_info.1,
+                                last_seq,
+                                missed,
+                            )
+                            .await
+                            {
+                                Ok(seq) => last_seq = last_seq.max(seq),
+                                Err(e) => {
+                                    log::error!("failed to resync lagged receiver: {e}");
+                                    break;
+                                }
+                            }
+                            continue;
+                        }
+                        Err(RecvError::Closed) => break,
+                    };
+
+                    if let Some(seq) = message.get("seq").and_then(Value::as_i64) {
+                        if seq <= last_seq {
+                            continue;
+                        }
+                        last_seq = seq;
+                    }
+                    if socket_send
+                        .send(WsMessage::text(message.to_string()))
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                    if let Some(close_frame) = system_close_frame(&message, &send_username) {
+                        let _ = socket_send.send(WsMessage::Close(Some(close_frame))).await;
+                        break;
+                    }
                 }
-            }
-        });
+            },
+        ).in_current_span());
 
-        let mut recv_task = tokio::spawn(async move {
-            while let Some(Ok(message)) = socket_recv.next().await {
-                // TODO: notify user in limited
-                if !RateLimiter::run(&headers, 10, 60, Arc::clone(&app_state.redis_client)).await {
-                    continue;
-                };
+        // chat is counted apart from the rest api, per ip and room by default
+        let subject = Subject::from_headers(&headers)
+            .user(username.clone())
+            .room(room_info.0);
+        let mut bandwidth = Bandwidth::new(
+            ws_config.bandwidth_bytes_per_sec,
+            ws_config.bandwidth_burst_bytes,
+            Instant::now(),
+        );
+        let mut recv_task = tokio::spawn(with_correlation_id(
+            self.connection_id.clone(),
+            async move {
+                let mut last_activity = Instant::now();
 
-                match message {
-                    WsMessage::Text(m) => {
-                        let mut db_tx = match app_state.db_pool.begin().await {
-                            Ok(v) => v,
-                            Err(e) => {
-                                log::error!("failed to start db tx: {e}");
-                                continue;
+                loop {
+                    let message =
+                        match time::timeout_at(last_activity + idle_timeout, socket_recv.next())
+                            .await
+                        {
+                            Ok(Some(Ok(v))) => v,
+                            Ok(Some(Err(e))) => {
+                                if let Some(frame_error) = FrameError::from_recv_error(e) {
+                                    log::info!("closing socket of {username}: {frame_error}");
+                                    let _ = direct_tx
+                                        .send(WsMessage::Close(Some(frame_error.close_frame())));
+                                }
+                                break;
+                            }
+                            Ok(None) => break,
+                            Err(_) => {
+                                log::info!("closing idle socket of {username}");
+                                let _ = direct_tx.send(WsMessage::Close(Some(CloseFrame {
+                                    code: close_code::NORMAL,
+                                    reason: "idle timeout".into(),
+                                })));
+                                break;
                             }
                         };
-                        let parse_message = Json(
-                            json!({ "user": username, "message": m.to_string(), "created_at": Utc::now().to_rfc2822() }),
-                        );
-                        let _ = Message::create(&mut db_tx, parse_message.to_string(), room_info.1)
-                            .await;
-
-                        match channel_tx.send(parse_message) {
-                            Ok(_) => {
-                                let _ = db_tx.commit().await;
+
+                    if let Err(frame_error) = bandwidth.take(frame_len(&message), Instant::now())
+                    {
+                        log::info!("closing socket of {username}: {frame_error}");
+                        let _ =
+                            direct_tx.send(WsMessage::Close(Some(frame_error.close_frame())));
+                        break;
+                    }
+
+                    match message {
+                        WsMessage::Pong(_) => {
+                            pong_notify.notify_one();
+                            continue;
+                        }
+                        WsMessage::Ping(_) => continue,
+                        // a client over its chat limit can still close cleanly
+                        WsMessage::Close(_) => break,
+                        _ => last_activity = Instant::now(),
+                    }
+
+                    // TODO: notify user in limited
+                    let policy = &app_state.config.rate_limit.chat;
+                    if matches!(message, WsMessage::Text(_) | WsMessage::Binary(_))
+                        && !RateLimiter::run(&subject, "chat", policy, &app_state).await
+                    {
+                        continue;
+                    };
+
+                    let WsMessage::Text(m) = message else {
+                        continue;
+                    };
+
+                    match serde_json::from_str(&m) {
+                        Ok(ClientFrame::Read { message_id }) => {
+                            if let Err(e) = Self::mark_read(
+                                &app_state,
+                                &channel_tx,
+                                &username,
+                                room_info.1,
+                                message_id,
+                            )
+                            .await
+                            {
+                                log::error!("failed to mark message as read: {e}");
+                                let _ = direct_tx
+                                    .send(error_frame("failed to mark message as read"));
                             }
-                            Err(_) => {
-                                let _ = db_tx.rollback().await;
+                            continue;
+                        }
+                        Ok(frame) => {
+                            if let Err(reason) = Self::moderate(
+                                &app_state,
+                                &channel_tx,
+                                &username,
+                                room_info,
+                                frame,
+                            )
+                            .await
+                            {
+                                let _ = direct_tx.send(error_frame(&reason));
                             }
+                            continue;
                         }
+                        Err(_) => (),
                     }
-                    WsMessage::Close(_) => {
-                        let _ = channel_tx.send(Json(Value::String(format!(
-                            "user {} leave the room",
-                            username
-                        ))));
-                        if channel_tx.receiver_count() == 1 {
-                            app_state.channels.lock().await.remove(&room_info.0);
+
+                    match Self::is_muted(&app_state, room_info.0, &username).await {
+                        Ok(true) => {
+                            let _ = direct_tx.send(error_frame("you are muted in this room"));
+                            continue;
+                        }
+                        Ok(false) => (),
+                        Err(e) => {
+                            log::error!("failed to check mute: {e}");
+                            app_state.metrics.record_redis_error();
+                        }
+                    }
+
+                    let text = match app_state.filters.apply(m.to_string(), room_info.0) {
+                        Ok(v) => v,
+                        Err(reason) => {
+                            log::debug!("refused message from {username}: {reason}");
+                            let _ = direct_tx.send(error_frame(&reason));
+                            continue;
+                        }
+                    };
+                    if let Some(reason) = app_state.spam.check(&username, room_info.0, &text) {
+                        if let Err(e) = Self::handle_spam(
+                            &app_state,
+                            &channel_tx,
+                            room_info,
+                            &username,
+                            &reason,
+                        )
+                        .await
+                        {
+                            log::error!("failed to handle spam: {e}");
+                        }
+                        let _ = direct_tx.send(error_frame(&format!("message dropped: {reason}")));
+                        continue;
+                    }
+
+                    let mut db_tx = match app_state.db_pool.begin().await {
+                        Ok(v) => v,
+                        Err(e) => {
+                            log::error!("failed to start db tx: {e}");
+                            continue;
+                        }
+                    };
+
+                    let mut parse_message = json!({ "user": username, "message": text, "created_at": Utc::now().to_rfc2822() });
+                    let mut last_seq = channel_tx.lock_seq().await;
+                    let seq = *last_seq + 1;
+                    match Message::create(
+                        &mut db_tx,
+                        parse_message.to_string(),
+                        room_info.1,
+                        seq,
+                    )
+                    .await
+                    {
+                        Ok(record) => parse_message["id"] = record.get_id().into(),
+                        Err(e) => {
+                            log::error!("failed to create message: {e}");
+                            continue;
                         }
-                        break;
                     }
-                    _ => (),
+                    // queued in the same transaction, a rolled back message sends nothing
+                    if app_state.config.webhook.enabled
+                        && let Err(e) = WebhookDelivery::enqueue(
+                            &mut db_tx,
+                            "message.created",
+                            room_info.0,
+                            webhooks::payload(
+                                "message.created",
+                                room_info.0,
+                                parse_message.clone(),
+                            ),
+                        )
+                        .await
+                    {
+                        log::error!("failed to queue message webhooks: {e}");
+                        continue;
+                    }
+                    if let Err(e) = db_tx.commit().await {
+                        log::error!("failed to commit message: {e}");
+                        continue;
+                    }
+
+                    *last_seq = seq;
+                    let _ = channel_tx.send_with_seq(parse_message, seq);
+                    drop(last_seq);
+                    app_state
+                        .metrics
+                        .messages_total
+                        .fetch_add(1, Ordering::Relaxed);
                 }
-            }
-        });
+            },
+        ).in_current_span());
 
         tokio::select! {
             _ = &mut send_task => recv_task.abort(),
-            _ = &mut recv_task => send_task.abort(),
+            _ = &mut recv_task => {
+                // let send_task flush frames queued by recv_task, like the idle close frame
+                let _ = time::timeout(Duration::from_secs(1), &mut send_task).await;
+                send_task.abort()
+            }
+        };
+    }
+}
+
+impl Drop for ConnectRoomWebSocket {
+    fn drop(&mut self) {
+        self.app_state
+            .metrics
+            .active_sockets
+            .fetch_sub(1, Ordering::Relaxed);
+
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
         };
+        let app_state = self.app_state.clone();
+        let room_uuid = self.room_info.0;
+        let channel_tx = self.channel_tx.clone();
+        let username = self.username.clone();
+
+        runtime.spawn(
+            with_correlation_id(self.connection_id.clone(), async move {
+                let mut channels = app_state.channels.lock().await;
+                let Some(room_channel) = channels.get_mut(&room_uuid) else {
+                    return;
+                };
+                if let Some(index) = room_channel.members.iter().position(|m| *m == username) {
+                    room_channel.members.remove(index);
+                }
+
+                if room_channel.members.is_empty() {
+                    channels.remove(&room_uuid);
+                } else {
+                    let _ = channel_tx.send(json!({
+                        "type": "leave",
+                        "user": username,
+                        "message": format!("user {} leave the room", username),
+                    }));
+                }
+                log::info!("{} left room {}", username, room_uuid);
+            })
+            .in_current_span(),
+        );
     }
 }
 
-// TODO: replace domain with real one
 pub async fn handle_rooms_list(
     State(app_state): State<AppState>,
     headers: HeaderMap,
 ) -> impl IntoResponse {
-    if !RateLimiter::run(&headers, 10, 60, app_state.redis_client).await {
+    let policy = &app_state.config.rate_limit.list;
+    if !RateLimiter::run(&Subject::from_headers(&headers), "list", policy, &app_state).await {
         return ApiResponse::build(false, Vec::new(), StatusCode::TOO_MANY_REQUESTS);
     }
 
+    let base_url = public_base_url(&app_state.config, &headers);
     let mut rooms = Vec::new();
     {
         let channels = app_state.channels.lock().await;
 
         rooms.reserve(channels.len());
 
-        for (uuid, tx) in channels.iter() {
-            let room_size = tx.receiver_count();
+        for (uuid, room_channel) in channels.iter() {
+            let room_size = room_channel.tx.receiver_count();
 
             rooms.push(RoomResponse::new(
                 uuid.to_string(),
                 room_size,
-                format!("ws://domain.com/room/{}", uuid),
+                format!("{}/room/{}", base_url, uuid),
             ));
         }
     }
 
-    rooms.sort_unstable_by(|a, b| b.room_size.cmp(&a.room_size));
+    rooms.sort_unstable_by_key(|room| Reverse(room.room_size));
 
     ApiResponse::build(true, rooms, StatusCode::OK)
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::test_utils::{get_redis_test_client, get_test_server};
-    use redis::AsyncCommands;
-    use serde_json::Value;
-    use uuid::Uuid;
+fn room_connect_url(app_state: &AppState, headers: &HeaderMap, room_uuid: &str) -> String {
+    format!(
+        "{}/room/{}",
+        public_base_url(&app_state.config, headers),
+        room_uuid
+    )
+}
 
-    #[tokio::test]
-    async fn test_handle_create_room() {
-        let server = get_test_server().await;
+pub async fn handle_room_members(
+    Path(uuid): Path<String>,
+    State(app_state): State<AppState>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let policy = &app_state.config.rate_limit.list;
+    if !RateLimiter::run(&Subject::from_headers(&headers), "list", policy, &app_state).await {
+        return ApiResponse::build(false, Vec::new(), StatusCode::TOO_MANY_REQUESTS);
+    }
 
-        let response = server
-            .post("/room/create")
-            .add_header("x-forwarded-for", "127.0.0.6")
-            .await;
-        assert_eq!(response.status_code(), 200);
+    let parsed_uuid = match Uuid::parse_str(&uuid) {
+        Ok(v) => v,
+        Err(_) => {
+            return ApiResponse::build(false, Vec::new(), StatusCode::NOT_FOUND);
+        }
+    };
 
-        let response_uuid =
-            Uuid::parse_str(response.json::<Value>()["data"].as_str().unwrap()).unwrap();
+    let members = app_state
+        .channels
+        .lock()
+        .await
+        .get(&parsed_uuid)
+        .map(|room_channel| room_channel.members.clone())
+        .unwrap_or_default();
 
-        let redis_client = get_redis_test_client().await;
-        let mut conn = redis_client
-            .get_multiplexed_async_connection()
-            .await
-            .unwrap();
-        let cache_key = format!("room:{}", response_uuid);
+    ApiResponse::build(true, members, StatusCode::OK)
+}
 
-        assert!(
+// only the holder of a session's resume token can read its unread counts
+pub async fn handle_unread_counts(
+    Query(query): Query<UnreadQuery>,
+    State(app_state): State<AppState>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let policy = &app_state.config.rate_limit.list;
+    if !RateLimiter::run(&Subject::from_headers(&headers), "list", policy, &app_state).await {
+        return ApiResponse::build(false, Vec::new(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    let username = match resumed_username(&app_state, &query.resume).await {
+        Ok(Some(v)) => v,
+        Ok(None) => return ApiResponse::build(false, Vec::new(), StatusCode::UNAUTHORIZED),
+        Err(e) => {
+            log::error!("failed to read resume token: {e}");
+            return ApiResponse::build(false, Vec::new(), StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    match RoomRead::unread_counts(None, Some(app_state.db_pool), username).await {
+        Ok(counts) => ApiResponse::build(
+            true,
+            counts
+                .iter()
+                .map(|count| {
+                    UnreadResponse::new(count.get_room_uuid().to_string(), count.get_unread())
+                })
+                .collect(),
+            StatusCode::OK,
+        ),
+        Err(e) => {
+            log::error!("failed to read unread counts: {e}");
+            ApiResponse::build(false, Vec::new(), StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn resumed_username(
+    app_state: &AppState,
+    resume_token: &str,
+) -> Result<Option<String>, DefaultError> {
+    let mut conn = app_state
+        .redis_client
+        .get_multiplexed_async_connection()
+        .await?;
+    match conn.get(format!("resume:{}", resume_token)).await {
+        Ok(v) => Ok(v),
+        Err(e) => {
+            app_state.metrics.record_redis_error();
+            Err(e.into())
+        }
+    }
+}
+
+async fn set_owner(
+    app_state: &AppState,
+    room_id: i32,
+    username: String,
+) -> Result<(), DefaultError> {
+    let mut tx = app_state.db_pool.begin().await?;
+    RoomRole::set(&mut tx, room_id, username.clone(), Role::Owner, &username).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+const SPAM_ACTOR: &str = "spam-detector";
+
+// token bucket over the bytes a client sends, pings and pongs included
+struct Bandwidth {
+    bytes_per_sec: usize,
+    burst: f64,
+    available: f64,
+    last: Instant,
+}
+
+impl Bandwidth {
+    fn new(bytes_per_sec: usize, burst: usize, now: Instant) -> Self {
+        Self {
+            bytes_per_sec,
+            burst: burst as f64,
+            available: burst as f64,
+            last: now,
+        }
+    }
+    fn take(&mut self, bytes: usize, now: Instant) -> Result<(), FrameError> {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.available = (self.available + elapsed * self.bytes_per_sec as f64).min(self.burst);
+        self.last = now;
+
+        if bytes as f64 > self.available {
+            return Err(FrameError::BandwidthExceeded {
+                bytes_per_sec: self.bytes_per_sec,
+            });
+        }
+        self.available -= bytes as f64;
+        Ok(())
+    }
+}
+
+fn frame_len(message: &WsMessage) -> usize {
+    match message {
+        WsMessage::Text(text) => text.len(),
+        WsMessage::Binary(data) | WsMessage::Ping(data) | WsMessage::Pong(data) => data.len(),
+        WsMessage::Close(_) => 0,
+    }
+}
+
+fn mute_key(room_uuid: Uuid, username: &str) -> String {
+    format!("mute:{}:{}", room_uuid, username)
+}
+
+fn error_frame(reason: &str) -> WsMessage {
+    WsMessage::text(json!({ "type": "error", "reason": reason }).to_string())
+}
+
+// system frames from the admin api and room moderators that end the receiving socket
+fn system_close_frame(frame: &Value, username: &str) -> Option<CloseFrame> {
+    if frame.get("type").and_then(Value::as_str) != Some("system") {
+        return None;
+    }
+    let targets_user = frame.get("user").and_then(Value::as_str) == Some(username);
+
+    match frame.get("event").and_then(Value::as_str) {
+        Some("room_closed") => Some(CloseFrame {
+            code: close_code::NORMAL,
+            reason: "room closed".into(),
+        }),
+        Some("kicked") if targets_user => Some(CloseFrame {
+            code: close_code::POLICY,
+            reason: "kicked".into(),
+        }),
+        Some("banned") if targets_user => Some(CloseFrame {
+            code: close_code::POLICY,
+            reason: "banned".into(),
+        }),
+        _ => None,
+    }
+}
+
+fn message_frame(message: &Message) -> Value {
+    let mut frame = serde_json::from_str(&message.get_message())
+        .unwrap_or_else(|_| Value::String(message.get_message()));
+    if let Value::Object(map) = &mut frame {
+        map.insert("id".to_string(), message.get_id().into());
+        if let Some(seq) = message.get_seq() {
+            map.insert("seq".to_string(), seq.into());
+        }
+    }
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Bandwidth;
+    use crate::{
+        handlers::init_app,
+        models::FrameError,
+        test_utils::{get_redis_test_client, get_test_app_state, get_test_server},
+    };
+    use axum_test::{TestServer, TestWebSocket, WsMessage};
+    use infra::db::models::{Ban, BanKind, Message, Room};
+    use redis::AsyncCommands;
+    use serde_json::{Value, json};
+    use shared::{
+        config::{
+            Config, FilterConfig, RateLimitConfig, RateLimitPolicy, RoomConfig, SpamConfig,
+            WsConfig,
+        },
+        filters::FilterPipeline,
+        helpers::generate_uuid_v4,
+        models::{AppState, RoomChannel},
+        spam::SpamDetector,
+    };
+    use std::{
+        sync::{Arc, atomic::Ordering},
+        time::Duration,
+    };
+    use tokio::time::Instant;
+    use uuid::Uuid;
+
+    async fn connect_new_room(server: &TestServer, ip: &str) -> (Uuid, TestWebSocket) {
+        let response = server
+            .post("/room/create")
+            .add_header("x-forwarded-for", ip)
+            .await;
+        let room_uuid =
+            Uuid::parse_str(response.json::<Value>()["data"].as_str().unwrap()).unwrap();
+
+        let mut socket = server
+            .get_websocket(&format!("/room/{}", room_uuid))
+            .add_header("x-forwarded-for", ip)
+            .await
+            .into_websocket()
+            .await;
+        socket.assert_receive_text("Connected").await;
+        assert_eq!(socket.receive_json::<Value>().await["type"], "session");
+        assert_eq!(socket.receive_json::<Value>().await["type"], "members");
+
+        (room_uuid, socket)
+    }
+    async fn ws_test_server(ws_config: WsConfig) -> (TestServer, AppState) {
+        let mut app_state = get_test_app_state().await;
+        app_state.config = Arc::new(Config {
+            ws: ws_config,
+            ..Config::default()
+        });
+        let server = TestServer::builder()
+            .http_transport()
+            .build(init_app(app_state.clone()).await)
+            .unwrap();
+
+        (server, app_state)
+    }
+
+    #[tokio::test]
+    async fn test_handle_create_room() {
+        let server = get_test_server().await;
+
+        let response = server
+            .post("/room/create")
+            .add_header("x-forwarded-for", "127.0.0.6")
+            .await;
+        assert_eq!(response.status_code(), 200);
+
+        let response_uuid =
+            Uuid::parse_str(response.json::<Value>()["data"].as_str().unwrap()).unwrap();
+
+        let redis_client = get_redis_test_client().await;
+        let mut conn = redis_client
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        let cache_key = format!("room:{}", response_uuid);
+
+        assert!(
             conn.get::<_, Option<String>>(&cache_key)
                 .await
                 .unwrap()
@@ -360,7 +1446,41 @@ mod tests {
         );
 
         conn.del::<_, ()>(cache_key).await.unwrap();
-        conn.del::<_, ()>("rate_limiter:127.0.0.6").await.unwrap();
+        conn.del::<_, ()>("rate_limiter:create_room:ip:127.0.0.6")
+            .await
+            .unwrap();
+    }
+    #[tokio::test]
+    async fn test_handle_create_room_returns_connect_url() {
+        let server = get_test_server().await;
+
+        let response = server
+            .post("/room/create")
+            .add_header("x-forwarded-for", "127.0.0.48")
+            .add_header("host", "chat.example.com")
+            .await;
+        assert_eq!(response.status_code(), 200);
+
+        let body = response.json::<Value>();
+        assert_eq!(
+            body["connect_url"],
+            format!(
+                "ws://chat.example.com/room/{}",
+                body["data"].as_str().unwrap()
+            )
+        );
+
+        let mut conn = get_redis_test_client()
+            .await
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        conn.del::<_, ()>(format!("room:{}", body["data"].as_str().unwrap()))
+            .await
+            .unwrap();
+        conn.del::<_, ()>("rate_limiter:create_room:ip:127.0.0.48")
+            .await
+            .unwrap();
     }
     #[tokio::test]
     async fn test_handle_create_room_return_429() {
@@ -372,7 +1492,7 @@ mod tests {
             .await
             .unwrap();
 
-        conn.set_ex::<_, _, ()>("rate_limiter:127.0.0.7", "0", 10)
+        conn.set_ex::<_, _, ()>("rate_limiter:create_room:ip:127.0.0.7", "0", 10)
             .await
             .unwrap();
 
@@ -407,7 +1527,7 @@ mod tests {
             .await
             .unwrap();
 
-        conn.set_ex::<_, _, ()>("rate_limiter:127.0.0.9", "0", 10)
+        conn.set_ex::<_, _, ()>("rate_limiter:list:ip:127.0.0.9", "0", 10)
             .await
             .unwrap();
 
@@ -417,4 +1537,992 @@ mod tests {
             .await;
         assert_eq!(response.status_code(), 429);
     }
+    #[tokio::test]
+    async fn test_handle_rooms_list_uses_public_url() {
+        let mut app_state = get_test_app_state().await;
+        let mut config = Config::default();
+        config.server.public_url = Some("wss://chat.example.com/api".to_string());
+        app_state.config = Arc::new(config);
+        let room_uuid = generate_uuid_v4();
+        app_state
+            .channels
+            .lock()
+            .await
+            .insert(room_uuid, RoomChannel::new(10, 0));
+        let server = TestServer::new(init_app(app_state).await).unwrap();
+
+        let response = server
+            .get("/room/list")
+            .add_header("x-forwarded-for", "127.0.0.28")
+            .await;
+        assert_eq!(response.status_code(), 200);
+
+        assert_eq!(
+            response.json::<Value>()["data"][0]["connect_url"],
+            format!("wss://chat.example.com/api/room/{}", room_uuid)
+        );
+    }
+    #[tokio::test]
+    async fn test_handle_room_members() {
+        let app_state = get_test_app_state().await;
+        let room_uuid = generate_uuid_v4();
+
+        let mut room_channel = RoomChannel::new(10, 0);
+        room_channel.members.push("anonymous_1".to_string());
+        room_channel.members.push("anonymous_2".to_string());
+        app_state
+            .channels
+            .lock()
+            .await
+            .insert(room_uuid, room_channel);
+
+        let server = TestServer::new(init_app(app_state).await).unwrap();
+        let response = server
+            .get(&format!("/room/{}/members", room_uuid))
+            .add_header("x-forwarded-for", "127.0.0.10")
+            .await;
+        assert_eq!(response.status_code(), 200);
+
+        assert_eq!(
+            response.json::<Value>()["data"],
+            serde_json::json!(["anonymous_1", "anonymous_2"])
+        );
+
+        let redis_client = get_redis_test_client().await;
+        let mut conn = redis_client
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        conn.del::<_, ()>("rate_limiter:list:ip:127.0.0.10")
+            .await
+            .unwrap();
+    }
+    #[tokio::test]
+    async fn test_handle_room_members_not_active_room() {
+        let server = get_test_server().await;
+
+        let response = server
+            .get(&format!("/room/{}/members", generate_uuid_v4()))
+            .add_header("x-forwarded-for", "127.0.0.11")
+            .await;
+        assert_eq!(response.status_code(), 200);
+
+        assert_eq!(
+            response.json::<Value>()["data"].as_array().unwrap().len(),
+            0
+        )
+    }
+    #[tokio::test]
+    async fn test_handle_room_members_invalid_uuid() {
+        let server = get_test_server().await;
+
+        let response = server
+            .get("/room/not-a-uuid/members")
+            .add_header("x-forwarded-for", "127.0.0.12")
+            .await;
+        assert_eq!(response.status_code(), 404);
+    }
+    #[tokio::test]
+    async fn test_handle_connect_room_ignores_read_from_other_room() {
+        let app_state = get_test_app_state().await;
+        let server = TestServer::builder()
+            .http_transport()
+            .build(init_app(app_state.clone()).await)
+            .unwrap();
+        let (_, mut socket) = connect_new_room(&server, "127.0.0.52").await;
+
+        let mut tx = app_state.db_pool.begin().await.unwrap();
+        let other_room_id = Room::create(&mut tx, None).await.unwrap().get_id();
+        let other_message = Message::create(&mut tx, "elsewhere".to_string(), other_room_id, 1)
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+
+        socket.send_text("hello-read").await;
+        let message_id = socket.receive_json::<Value>().await["id"].clone();
+
+        socket
+            .send_json(&json!({ "type": "read", "message_id": other_message.get_id() }))
+            .await;
+        socket
+            .send_json(&json!({ "type": "read", "message_id": message_id }))
+            .await;
+
+        // no error frame and no read event for the foreign message
+        let frame = socket.receive_json::<Value>().await;
+        assert_eq!(frame["type"], "read");
+        assert_eq!(frame["message_id"], message_id);
+
+        let mut conn = get_redis_test_client()
+            .await
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        conn.del::<_, ()>("rate_limiter:create_room:ip:127.0.0.52")
+            .await
+            .unwrap();
+    }
+    #[tokio::test]
+    async fn test_handle_unread_counts() {
+        let server = get_test_server().await;
+        let resume_token = format!("unread-{}", generate_uuid_v4());
+        let redis_client = get_redis_test_client().await;
+        let mut conn = redis_client
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        conn.set_ex::<_, _, ()>(format!("resume:{}", resume_token), "anonymous_nobody", 60)
+            .await
+            .unwrap();
+
+        let response = server
+            .get("/room/unread")
+            .add_query_param("resume", &resume_token)
+            .add_header("x-forwarded-for", "127.0.0.13")
+            .await;
+        assert_eq!(response.status_code(), 200);
+
+        assert_eq!(
+            response.json::<Value>()["data"].as_array().unwrap().len(),
+            0
+        );
+
+        conn.del::<_, ()>(format!("resume:{}", resume_token))
+            .await
+            .unwrap();
+    }
+    #[tokio::test]
+    async fn test_handle_unread_counts_missing_resume_token() {
+        let server = get_test_server().await;
+
+        let response = server
+            .get("/room/unread")
+            .add_query_param("username", "anonymous_nobody")
+            .add_header("x-forwarded-for", "127.0.0.14")
+            .await;
+        assert_eq!(response.status_code(), 400);
+    }
+    #[tokio::test]
+    async fn test_handle_unread_counts_unknown_resume_token() {
+        let server = get_test_server().await;
+
+        let response = server
+            .get("/room/unread")
+            .add_query_param("resume", "not-a-session")
+            .add_header("x-forwarded-for", "127.0.0.43")
+            .await;
+        assert_eq!(response.status_code(), 401);
+    }
+    #[tokio::test]
+    async fn test_handle_connect_room_banned_ip() {
+        let app_state = get_test_app_state().await;
+        let server = TestServer::builder()
+            .http_transport()
+            .build(init_app(app_state.clone()).await)
+            .unwrap();
+        let mut tx = app_state.db_pool.begin().await.unwrap();
+        Ban::create(&mut tx, BanKind::Ip, "127.0.0.30".to_string(), None, "test")
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+
+        let response = server
+            .post("/room/create")
+            .add_header("x-forwarded-for", "127.0.0.30")
+            .await;
+        let room_uuid = response.json::<Value>()["data"].clone();
+
+        let response = server
+            .get_websocket(&format!("/room/{}", room_uuid.as_str().unwrap()))
+            .add_header("x-forwarded-for", "127.0.0.30")
+            .await;
+        assert_eq!(response.status_code(), 403);
+
+        let mut tx = app_state.db_pool.begin().await.unwrap();
+        Ban::delete(&mut tx, BanKind::Ip, "127.0.0.30".to_string(), "test")
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+        let mut conn = get_redis_test_client()
+            .await
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        conn.del::<_, ()>("rate_limiter:create_room:ip:127.0.0.30")
+            .await
+            .unwrap();
+    }
+    #[tokio::test]
+    async fn test_handle_connect_room_filters_messages() {
+        let mut app_state = get_test_app_state().await;
+        let filter_config = FilterConfig {
+            max_length: 20,
+            blocklist: vec!["darn".to_string()],
+            ..FilterConfig::default()
+        };
+        app_state.filters = Arc::new(FilterPipeline::from_config(&filter_config));
+        app_state.config = Arc::new(Config {
+            filter: filter_config,
+            ..Config::default()
+        });
+        let server = TestServer::builder()
+            .http_transport()
+            .build(init_app(app_state).await)
+            .unwrap();
+        let (_, mut socket) = connect_new_room(&server, "127.0.0.38").await;
+
+        socket.send_text("darn\u{7} it").await;
+        assert_eq!(socket.receive_json::<Value>().await["message"], "**** it");
+
+        socket.send_text("a".repeat(21)).await;
+        let error = socket.receive_json::<Value>().await;
+        assert_eq!(error["type"], "error");
+        assert_eq!(error["reason"], "message is longer than 20 characters");
+
+        let mut conn = get_redis_test_client()
+            .await
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        conn.del::<_, ()>("rate_limiter:create_room:ip:127.0.0.38")
+            .await
+            .unwrap();
+    }
+    #[tokio::test]
+    async fn test_handle_connect_room_mutes_spammers() {
+        let mut app_state = get_test_app_state().await;
+        let spam_config = SpamConfig {
+            max_duplicates: 2,
+            new_account_secs: 0,
+            ..SpamConfig::default()
+        };
+        app_state.spam = Arc::new(SpamDetector::new(spam_config.clone()));
+        app_state.config = Arc::new(Config {
+            spam: spam_config,
+            ..Config::default()
+        });
+        let server = TestServer::builder()
+            .http_transport()
+            .build(init_app(app_state).await)
+            .unwrap();
+        let (room_uuid, mut socket) = connect_new_room(&server, "127.0.0.39").await;
+
+        for _ in 0..2 {
+            socket.send_text("buy cheap pills").await;
+            assert_eq!(
+                socket.receive_json::<Value>().await["message"],
+                "buy cheap pills"
+            );
+        }
+        socket.send_text("Buy cheap pills!").await;
+        let mut frames = [
+            socket.receive_json::<Value>().await,
+            socket.receive_json::<Value>().await,
+        ];
+        frames.sort_by_key(|frame| frame["type"].to_string());
+        assert_eq!(frames[0]["reason"], "message dropped: repeated message");
+        assert_eq!(frames[1]["event"], "muted");
+        assert_eq!(frames[1]["by"], "spam-detector");
+
+        socket.send_text("hello?").await;
+        assert_eq!(
+            socket.receive_json::<Value>().await["reason"],
+            "you are muted in this room"
+        );
+
+        let mut conn = get_redis_test_client()
+            .await
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        conn.del::<_, ()>("rate_limiter:create_room:ip:127.0.0.39")
+            .await
+            .unwrap();
+        let pattern = format!("mute:{}:*", room_uuid);
+        for key in conn.keys::<_, Vec<String>>(pattern).await.unwrap() {
+            conn.del::<_, ()>(key).await.unwrap();
+        }
+    }
+    #[tokio::test]
+    async fn test_handle_connect_room_moderation() {
+        let (server, _) = ws_test_server(WsConfig::default()).await;
+        let (room_uuid, mut owner) = connect_new_room(&server, "127.0.0.36").await;
+        let room_path = format!("/room/{}", room_uuid);
+
+        let mut member = server
+            .get_websocket(&room_path)
+            .add_header("x-forwarded-for", "127.0.0.37")
+            .await
+            .into_websocket()
+            .await;
+        member.assert_receive_text("Connected").await;
+        let session = member.receive_json::<Value>().await;
+        let member_name = session["user"].as_str().unwrap().to_string();
+        assert_eq!(member.receive_json::<Value>().await["type"], "members");
+        assert_eq!(owner.receive_json::<Value>().await["type"], "join");
+
+        member
+            .send_json(&json!({ "type": "kick", "user": "anyone" }))
+            .await;
+        let error = member.receive_json::<Value>().await;
+        assert_eq!(error["type"], "error");
+        assert_eq!(error["reason"], "only a room moderator can do that");
+
+        owner
+            .send_json(&json!({ "type": "mute", "user": member_name, "duration": 60 }))
+            .await;
+        assert_eq!(owner.receive_json::<Value>().await["event"], "muted");
+        assert_eq!(member.receive_json::<Value>().await["event"], "muted");
+        member.send_text("can anyone hear me").await;
+        assert_eq!(
+            member.receive_json::<Value>().await["reason"],
+            "you are muted in this room"
+        );
+
+        owner
+            .send_json(&json!({ "type": "mute", "user": member_name, "duration": 0 }))
+            .await;
+        assert_eq!(owner.receive_json::<Value>().await["event"], "unmuted");
+        assert_eq!(member.receive_json::<Value>().await["event"], "unmuted");
+
+        owner
+            .send_json(&json!({ "type": "ban", "user": member_name, "reason": "spam" }))
+            .await;
+        let banned = member.receive_json::<Value>().await;
+        assert_eq!(banned["event"], "banned");
+        assert_eq!(banned["reason"], "spam");
+        match member.receive_message().await {
+            WsMessage::Close(Some(frame)) => assert_eq!(frame.reason, "banned"),
+            other => panic!("expected close frame, got {other:?}"),
+        }
+
+        let response = server
+            .get_websocket(&room_path)
+            .add_query_param("resume", session["resume_token"].as_str().unwrap())
+            .add_header("x-forwarded-for", "127.0.0.37")
+            .await;
+        assert_eq!(response.status_code(), 403);
+
+        let mut conn = get_redis_test_client()
+            .await
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        conn.del::<_, ()>(&[
+            "rate_limiter:create_room:ip:127.0.0.36",
+            "rate_limiter:create_room:ip:127.0.0.37",
+        ])
+        .await
+        .unwrap();
+    }
+    #[tokio::test]
+    async fn test_handle_connect_room_resume_session() {
+        let server = TestServer::builder()
+            .http_transport()
+            .build(init_app(get_test_app_state().await).await)
+            .unwrap();
+
+        let response = server
+            .post("/room/create")
+            .add_header("x-forwarded-for", "127.0.0.15")
+            .await;
+        let room_path = format!(
+            "/room/{}",
+            response.json::<Value>()["data"].as_str().unwrap()
+        );
+
+        let mut socket = server
+            .get_websocket(&room_path)
+            .add_header("x-forwarded-for", "127.0.0.15")
+            .await
+            .into_websocket()
+            .await;
+        socket.assert_receive_text("Connected").await;
+        let session = socket.receive_json::<Value>().await;
+        assert_eq!(session["type"], "session");
+        assert_eq!(socket.receive_json::<Value>().await["type"], "members");
+
+        socket.send_text("hello-resume").await;
+        let message = socket.receive_json::<Value>().await;
+        assert_eq!(message["message"], "hello-resume");
+        // the join event doesn't take a seq, only stored messages do
+        let seq = message["seq"].as_i64().unwrap();
+        assert_eq!(seq, 1);
+        socket.close().await;
+
+        let mut socket = server
+            .get_websocket(&room_path)
+            .add_query_param("resume", session["resume_token"].as_str().unwrap())
+            .add_query_param("since", seq - 1)
+            .add_header("x-forwarded-for", "127.0.0.15")
+            .await
+            .into_websocket()
+            .await;
+        socket.assert_receive_text("Connected").await;
+        let resumed = socket.receive_json::<Value>().await;
+        assert_eq!(resumed["user"], session["user"]);
+        assert_eq!(resumed["resume_token"], session["resume_token"]);
+        assert_eq!(socket.receive_json::<Value>().await["type"], "members");
+
+        let replayed = socket.receive_json::<Value>().await;
+        assert_eq!(replayed["message"], "hello-resume");
+        assert_eq!(replayed["seq"], seq);
+        let history = socket.receive_json::<Value>().await;
+        assert_eq!(history["type"], "history");
+        assert_eq!(history["has_more"], false);
+        assert_eq!(history["next_since"], seq);
+
+        let redis_client = get_redis_test_client().await;
+        let mut conn = redis_client
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        conn.del::<_, ()>("rate_limiter:create_room:ip:127.0.0.15")
+            .await
+            .unwrap();
+    }
+    #[tokio::test]
+    async fn test_handle_connect_room_since_reports_truncation() {
+        let mut app_state = get_test_app_state().await;
+        app_state.config = Arc::new(Config {
+            room: RoomConfig {
+                history_limit: 2,
+                ..RoomConfig::default()
+            },
+            ..Config::default()
+        });
+        let server = TestServer::builder()
+            .http_transport()
+            .build(init_app(app_state).await)
+            .unwrap();
+        let (room_uuid, mut socket) = connect_new_room(&server, "127.0.0.44").await;
+
+        for i in 0..3 {
+            socket.send_text(format!("message {i}")).await;
+            assert_eq!(
+                socket.receive_json::<Value>().await["message"],
+                format!("message {i}")
+            );
+        }
+        socket.close().await;
+
+        let mut socket = server
+            .get_websocket(&format!("/room/{}", room_uuid))
+            .add_query_param("since", 0)
+            .add_header("x-forwarded-for", "127.0.0.44")
+            .await
+            .into_websocket()
+            .await;
+        socket.assert_receive_text("Connected").await;
+        assert_eq!(socket.receive_json::<Value>().await["type"], "session");
+        assert_eq!(socket.receive_json::<Value>().await["type"], "members");
+        assert_eq!(socket.receive_json::<Value>().await["seq"], 1);
+        assert_eq!(socket.receive_json::<Value>().await["seq"], 2);
+        let history = socket.receive_json::<Value>().await;
+        assert_eq!(history["type"], "history");
+        assert_eq!(history["has_more"], true);
+        assert_eq!(history["next_since"], 2);
+
+        let mut conn = get_redis_test_client()
+            .await
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        conn.del::<_, ()>("rate_limiter:create_room:ip:127.0.0.44")
+            .await
+            .unwrap();
+    }
+    #[tokio::test]
+    async fn test_handle_connect_room_resync_pages_through_gap() {
+        let mut app_state = get_test_app_state().await;
+        app_state.config = Arc::new(Config {
+            room: RoomConfig {
+                history_limit: 1,
+                ..RoomConfig::default()
+            },
+            ws: WsConfig {
+                broadcast_capacity: 1,
+                ..WsConfig::default()
+            },
+            ..Config::default()
+        });
+        let server = TestServer::builder()
+            .http_transport()
+            .build(init_app(app_state.clone()).await)
+            .unwrap();
+        let (room_uuid, mut socket) = connect_new_room(&server, "127.0.0.45").await;
+
+        let channel_tx = loop {
+            if let Some(room_channel) = app_state.channels.lock().await.get(&room_uuid)
+                && room_channel.tx.receiver_count() == 1
+            {
+                break room_channel.tx.clone();
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        let room_id = Room::read(None, Some(Arc::clone(&app_state.db_pool)), Some(room_uuid))
+            .await
+            .unwrap()[0]
+            .get_id();
+        let mut tx = app_state.db_pool.begin().await.unwrap();
+        for seq in 1..=3 {
+            Message::create(&mut tx, json!({ "message": seq }).to_string(), room_id, seq)
+                .await
+                .unwrap();
+        }
+        tx.commit().await.unwrap();
+        for _ in 0..10 {
+            channel_tx.send(json!({ "type": "test" })).unwrap();
+        }
+
+        assert_eq!(socket.receive_json::<Value>().await["type"], "lagged");
+        for seq in 1..=3 {
+            assert_eq!(socket.receive_json::<Value>().await["seq"], seq);
+        }
+        assert_eq!(socket.receive_json::<Value>().await["type"], "test");
+
+        let mut conn = get_redis_test_client()
+            .await
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        conn.del::<_, ()>("rate_limiter:create_room:ip:127.0.0.45")
+            .await
+            .unwrap();
+    }
+    #[tokio::test]
+    async fn test_handle_connect_room_lagged_receiver() {
+        let (server, app_state) = ws_test_server(WsConfig {
+            broadcast_capacity: 1,
+            ..WsConfig::default()
+        })
+        .await;
+        let (room_uuid, mut socket) = connect_new_room(&server, "127.0.0.16").await;
+
+        let channel_tx = loop {
+            if let Some(room_channel) = app_state.channels.lock().await.get(&room_uuid)
+                && room_channel.tx.receiver_count() == 1
+            {
+                break room_channel.tx.clone();
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        for _ in 0..10 {
+            channel_tx.send(json!({ "type": "test" })).unwrap();
+        }
+
+        let lagged = socket.receive_json::<Value>().await;
+        assert_eq!(lagged["type"], "lagged");
+        assert_eq!(lagged["missed"], 9);
+        assert_eq!(socket.receive_json::<Value>().await["type"], "test");
+        assert_eq!(
+            app_state
+                .metrics
+                .broadcast_lag_events
+                .load(Ordering::Relaxed),
+            1
+        );
+
+        let redis_client = get_redis_test_client().await;
+        let mut conn = redis_client
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        conn.del::<_, ()>("rate_limiter:create_room:ip:127.0.0.16")
+            .await
+            .unwrap();
+    }
+    #[tokio::test]
+    async fn test_handle_connect_room_concurrent_senders() {
+        let mut app_state = get_test_app_state().await;
+        let spam_config = SpamConfig {
+            enabled: false,
+            ..SpamConfig::default()
+        };
+        app_state.spam = Arc::new(SpamDetector::new(spam_config.clone()));
+        app_state.config = Arc::new(Config {
+            spam: spam_config,
+            ..Config::default()
+        });
+        let server = TestServer::builder()
+            .http_transport()
+            .build(init_app(app_state).await)
+            .unwrap();
+        let (room_uuid, mut first) = connect_new_room(&server, "127.0.0.46").await;
+        let mut second = server
+            .get_websocket(&format!("/room/{}", room_uuid))
+            .add_header("x-forwarded-for", "127.0.0.47")
+            .await
+            .into_websocket()
+            .await;
+        second.assert_receive_text("Connected").await;
+        assert_eq!(second.receive_json::<Value>().await["type"], "session");
+        assert_eq!(second.receive_json::<Value>().await["type"], "members");
+        assert_eq!(first.receive_json::<Value>().await["type"], "join");
+
+        tokio::join!(
+            async {
+                for i in 0..5 {
+                    first.send_text(format!("first {i}")).await;
+                }
+            },
+            async {
+                for i in 0..5 {
+                    second.send_text(format!("second {i}")).await;
+                }
+            },
+        );
+
+        // every stored message reaches both members, in seq order
+        for socket in [&mut first, &mut second] {
+            let seqs = tokio::time::timeout(Duration::from_secs(5), async {
+                let mut seqs = Vec::new();
+                while seqs.len() < 10 {
+                    seqs.push(
+                        socket.receive_json::<Value>().await["seq"]
+                            .as_i64()
+                            .unwrap(),
+                    );
+                }
+                seqs
+            })
+            .await
+            .expect("a message was dropped");
+            assert_eq!(seqs, (1..=10).collect::<Vec<_>>());
+        }
+
+        let mut conn = get_redis_test_client()
+            .await
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        conn.del::<_, ()>(&[
+            "rate_limiter:create_room:ip:127.0.0.46",
+            "rate_limiter:create_room:ip:127.0.0.47",
+        ])
+        .await
+        .unwrap();
+    }
+    #[tokio::test]
+    async fn test_handle_connect_room_delivers_messages_sent_during_join() {
+        let mut app_state = get_test_app_state().await;
+        let spam_config = SpamConfig {
+            enabled: false,
+            ..SpamConfig::default()
+        };
+        app_state.spam = Arc::new(SpamDetector::new(spam_config.clone()));
+        app_state.config = Arc::new(Config {
+            spam: spam_config,
+            ..Config::default()
+        });
+        let server = TestServer::builder()
+            .http_transport()
+            .build(init_app(app_state).await)
+            .unwrap();
+        let (room_uuid, mut first) = connect_new_room(&server, "127.0.0.49").await;
+
+        let (_, mut second) = tokio::join!(
+            async {
+                for i in 0..10 {
+                    first.send_text(format!("message {i}")).await;
+                }
+            },
+            async {
+                let mut socket = server
+                    .get_websocket(&format!("/room/{}", room_uuid))
+                    .add_header("x-forwarded-for", "127.0.0.50")
+                    .await
+                    .into_websocket()
+                    .await;
+                socket.assert_receive_text("Connected").await;
+                socket
+            },
+        );
+
+        // every message shows up once, either in the history or live
+        let seqs = tokio::time::timeout(Duration::from_secs(5), async {
+            let mut seqs = Vec::new();
+            while seqs.len() < 10 {
+                if let Some(seq) = second.receive_json::<Value>().await["seq"].as_i64() {
+                    seqs.push(seq);
+                }
+            }
+            seqs
+        })
+        .await
+        .expect("a message sent during the join was dropped");
+        assert_eq!(seqs, (1..=10).collect::<Vec<_>>());
+
+        let mut conn = get_redis_test_client()
+            .await
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        conn.del::<_, ()>(&[
+            "rate_limiter:create_room:ip:127.0.0.49",
+            "rate_limiter:create_room:ip:127.0.0.50",
+        ])
+        .await
+        .unwrap();
+    }
+    #[tokio::test]
+    async fn test_handle_connect_room_sends_ping() {
+        let (server, _) = ws_test_server(WsConfig {
+            ping_interval_secs: 1,
+            ..WsConfig::default()
+        })
+        .await;
+        let (_, mut socket) = connect_new_room(&server, "127.0.0.17").await;
+
+        assert!(matches!(socket.receive_message().await, WsMessage::Ping(_)));
+
+        let redis_client = get_redis_test_client().await;
+        let mut conn = redis_client
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        conn.del::<_, ()>("rate_limiter:create_room:ip:127.0.0.17")
+            .await
+            .unwrap();
+    }
+    #[tokio::test]
+    async fn test_handle_connect_room_rejects_large_frames() {
+        let (server, _) = ws_test_server(WsConfig {
+            max_frame_bytes: 1024,
+            max_message_bytes: 1024,
+            ..WsConfig::default()
+        })
+        .await;
+        let (_, mut socket) = connect_new_room(&server, "127.0.0.40").await;
+
+        socket.send_text("a".repeat(2048)).await;
+        match socket.receive_message().await {
+            WsMessage::Close(Some(frame)) => {
+                assert_eq!(u16::from(frame.code), 1009);
+                assert_eq!(frame.reason, "message is larger than 1024 bytes");
+            }
+            other => panic!("expected close frame, got {other:?}"),
+        }
+
+        let redis_client = get_redis_test_client().await;
+        let mut conn = redis_client
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        conn.del::<_, ()>("rate_limiter:create_room:ip:127.0.0.40")
+            .await
+            .unwrap();
+    }
+    #[tokio::test]
+    async fn test_handle_connect_room_limits_bandwidth() {
+        let (server, _) = ws_test_server(WsConfig {
+            max_frame_bytes: 1024,
+            max_message_bytes: 1024,
+            bandwidth_bytes_per_sec: 100,
+            bandwidth_burst_bytes: 1024,
+            ..WsConfig::default()
+        })
+        .await;
+        let (_, mut socket) = connect_new_room(&server, "127.0.0.41").await;
+
+        socket
+            .send_message(WsMessage::Binary(vec![0; 600].into()))
+            .await;
+        socket
+            .send_message(WsMessage::Binary(vec![0; 600].into()))
+            .await;
+        match socket.receive_message().await {
+            WsMessage::Close(Some(frame)) => assert_eq!(u16::from(frame.code), 1008),
+            other => panic!("expected close frame, got {other:?}"),
+        }
+
+        let redis_client = get_redis_test_client().await;
+        let mut conn = redis_client
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        conn.del::<_, ()>("rate_limiter:create_room:ip:127.0.0.41")
+            .await
+            .unwrap();
+    }
+    #[test]
+    fn test_bandwidth_refills() {
+        let start = Instant::now();
+        let mut bandwidth = Bandwidth::new(100, 200, start);
+
+        assert!(bandwidth.take(200, start).is_ok());
+        assert_eq!(
+            bandwidth.take(1, start),
+            Err(FrameError::BandwidthExceeded { bytes_per_sec: 100 })
+        );
+        assert!(bandwidth.take(100, start + Duration::from_secs(1)).is_ok());
+        // never refills past the burst
+        assert!(
+            bandwidth
+                .take(201, start + Duration::from_secs(60))
+                .is_err()
+        );
+    }
+    #[tokio::test]
+    async fn test_handle_connect_room_closes_idle_socket() {
+        let (server, _) = ws_test_server(WsConfig {
+            idle_timeout_secs: 1,
+            ..WsConfig::default()
+        })
+        .await;
+        let (_, mut socket) = connect_new_room(&server, "127.0.0.18").await;
+
+        match socket.receive_message().await {
+            WsMessage::Close(Some(frame)) => assert_eq!(frame.reason, "idle timeout"),
+            other => panic!("expected close frame, got {other:?}"),
+        }
+
+        let redis_client = get_redis_test_client().await;
+        let mut conn = redis_client
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        conn.del::<_, ()>("rate_limiter:create_room:ip:127.0.0.18")
+            .await
+            .unwrap();
+    }
+    async fn wait_for_channels(app_state: &AppState, expected: impl Fn(usize) -> bool) {
+        for _ in 0..100 {
+            if expected(app_state.channels.lock().await.len()) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("channels did not reach the expected state");
+    }
+
+    #[tokio::test]
+    async fn test_handle_connect_room_cleanup_on_close() {
+        let (server, app_state) = ws_test_server(WsConfig::default()).await;
+        let (_, socket) = connect_new_room(&server, "127.0.0.19").await;
+        wait_for_channels(&app_state, |len| len == 1).await;
+
+        socket.close().await;
+        wait_for_channels(&app_state, |len| len == 0).await;
+
+        let redis_client = get_redis_test_client().await;
+        let mut conn = redis_client
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        conn.del::<_, ()>("rate_limiter:create_room:ip:127.0.0.19")
+            .await
+            .unwrap();
+    }
+    #[tokio::test]
+    async fn test_handle_connect_room_closes_over_chat_limit() {
+        let mut app_state = get_test_app_state().await;
+        app_state.config = Arc::new(Config {
+            rate_limit: RateLimitConfig {
+                chat: RateLimitPolicy::new(1, 60),
+                ..RateLimitConfig::default()
+            },
+            ..Config::default()
+        });
+        let server = TestServer::builder()
+            .http_transport()
+            .build(init_app(app_state.clone()).await)
+            .unwrap();
+        let (_, mut socket) = connect_new_room(&server, "127.0.0.51").await;
+        wait_for_channels(&app_state, |len| len == 1).await;
+
+        for i in 0..3 {
+            socket.send_text(format!("message {i}")).await;
+        }
+        socket.close().await;
+        wait_for_channels(&app_state, |len| len == 0).await;
+
+        let mut conn = get_redis_test_client()
+            .await
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        conn.del::<_, ()>(&[
+            "rate_limiter:create_room:ip:127.0.0.51",
+            "rate_limiter:chat:ip:127.0.0.51",
+        ])
+        .await
+        .unwrap();
+    }
+    #[tokio::test]
+    async fn test_handle_connect_room_cleanup_on_abrupt_disconnect() {
+        let (server, app_state) = ws_test_server(WsConfig::default()).await;
+        let (_, socket) = connect_new_room(&server, "127.0.0.20").await;
+        wait_for_channels(&app_state, |len| len == 1).await;
+
+        drop(socket);
+        wait_for_channels(&app_state, |len| len == 0).await;
+
+        let redis_client = get_redis_test_client().await;
+        let mut conn = redis_client
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        conn.del::<_, ()>("rate_limiter:create_room:ip:127.0.0.20")
+            .await
+            .unwrap();
+    }
+    #[tokio::test]
+    async fn test_handle_connect_room_leave_keeps_active_room() {
+        let (server, app_state) = ws_test_server(WsConfig::default()).await;
+        let (room_uuid, mut socket) = connect_new_room(&server, "127.0.0.21").await;
+
+        let mut other_socket = server
+            .get_websocket(&format!("/room/{}", room_uuid))
+            .add_header("x-forwarded-for", "127.0.0.21")
+            .await
+            .into_websocket()
+            .await;
+        other_socket.assert_receive_text("Connected").await;
+        let other_session = other_socket.receive_json::<Value>().await;
+        assert_eq!(socket.receive_json::<Value>().await["type"], "join");
+
+        other_socket.close().await;
+        let leave = socket.receive_json::<Value>().await;
+        assert_eq!(leave["type"], "leave");
+        assert_eq!(leave["user"], other_session["user"]);
+
+        let channels = app_state.channels.lock().await;
+        assert_eq!(channels.len(), 1);
+        assert_eq!(channels.get(&room_uuid).unwrap().members.len(), 1);
+        drop(channels);
+
+        let redis_client = get_redis_test_client().await;
+        let mut conn = redis_client
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        conn.del::<_, ()>("rate_limiter:create_room:ip:127.0.0.21")
+            .await
+            .unwrap();
+    }
+    #[tokio::test]
+    async fn test_handle_connect_room_shutdown() {
+        let (server, app_state) = ws_test_server(WsConfig::default()).await;
+        let (_, mut socket) = connect_new_room(&server, "127.0.0.22").await;
+
+        app_state.shutdown.send_replace(true);
+
+        let restarting = socket.receive_json::<Value>().await;
+        assert_eq!(restarting["type"], "system");
+        assert_eq!(restarting["event"], "restarting");
+        match socket.receive_message().await {
+            WsMessage::Close(Some(frame)) => assert_eq!(u16::from(frame.code), 1012),
+            other => panic!("expected close frame, got {other:?}"),
+        }
+
+        let redis_client = get_redis_test_client().await;
+        let mut conn = redis_client
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        conn.del::<_, ()>("rate_limiter:create_room:ip:127.0.0.22")
+            .await
+            .unwrap();
+    }
 }
@@ -1,16 +1,30 @@
-use std::{collections::HashMap, process, sync::Arc};
+use std::{collections::HashMap, env, net::SocketAddr, process, sync::Arc, time::Duration};
 
+use axum::Router;
+use axum_server::Handle;
 use dotenvy::dotenv;
-use infra::{cache::get_redis_client, db::create_pool, logging::init_logger};
-use shared::models::AppState;
-use tokio::{net::TcpListener, sync::Mutex};
+use infra::{
+    cache::get_redis_client, db::create_pool, logging::init_logger, telemetry::init_tracing,
+};
+use shared::{config::Config, models::AppState};
+use tokio::{net::TcpListener, sync::Mutex, time};
 
-use crate::handlers::init_app;
+use crate::{
+    handlers::init_app,
+    migrate::{prepare_schema, run_command},
+    shutdown::{drain_connections, shutdown_signal},
+    tls::{https_port, load_rustls_config, serve_redirect, watch_certificates},
+};
 
 mod handlers;
+mod migrate;
 mod models;
 mod rate_limiter;
+mod request_id;
+mod shutdown;
+mod tls;
 mod utils;
+mod webhooks;
 
 #[cfg(test)]
 mod test_utils;
@@ -18,33 +32,127 @@ mod test_utils;
 #[tokio::main]
 async fn main() {
     dotenv().ok();
-    init_logger();
+    let config = Config::load();
+    init_logger(
+        &config
+            .as_ref()
+            .map(|config| config.log.clone())
+            .unwrap_or_default(),
+    );
 
-    let db_pool = Arc::new(create_pool().await.unwrap_or_else(|error| {
+    let config = Arc::new(config.unwrap_or_else(|error| {
+        log::error!("failed to load config: {error}");
+        process::exit(1)
+    }));
+    let tracer_provider = init_tracing(&config.telemetry, &config.log).unwrap_or_else(|error| {
+        log::error!("failed to start tracing: {error}");
+        process::exit(1)
+    });
+    let db_pool = Arc::new(create_pool(&config.database).await.unwrap_or_else(|error| {
         log::error!("failed to create db pool: {error}");
         process::exit(1)
     }));
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("migrate") {
+        if let Err(error) = run_command(&args[1..], &db_pool).await {
+            log::error!("migrate failed: {error}");
+            process::exit(1)
+        }
+        return;
+    }
+    if let Err(error) = prepare_schema(config.database.migrate_on_startup, &db_pool).await {
+        log::error!("failed to prepare database schema: {error}");
+        process::exit(1)
+    }
+
     let redis_client = Arc::new(get_redis_client().unwrap_or_else(|error| {
         log::error!("failed to create redis client: {error}");
         process::exit(1)
     }));
 
-    let app = init_app(AppState::new(
+    let app_state = AppState::new(
         db_pool,
         redis_client,
         Arc::new(Mutex::new(HashMap::new())),
-    ))
-    .await;
+        Arc::clone(&config),
+    );
+    let app = init_app(app_state.clone()).await;
+    let webhook_worker = webhooks::spawn_worker(app_state.clone());
+
+    if config.tls.enabled {
+        serve_tls(app, app_state.clone()).await;
+    } else {
+        let listener = TcpListener::bind(&config.server.bind)
+            .await
+            .unwrap_or_else(|error| {
+                log::error!("failed to start listener: {error}");
+                process::exit(1)
+            });
+
+        axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown_signal(app_state.clone()))
+            .await
+            .unwrap_or_else(|error| {
+                log::error!("failed to start server: {error}");
+                std::process::exit(1)
+            });
+    }
 
-    let listener = TcpListener::bind("0.0.0.0:3000")
+    drain_connections(&app_state).await;
+    // the worker stops on the shutdown signal, let in-flight deliveries record their outcome
+    if let Some(worker) = webhook_worker {
+        let timeout = Duration::from_secs(config.webhook.timeout_secs + 1);
+        if time::timeout(timeout, worker).await.is_err() {
+            log::warn!("webhook worker did not stop in time");
+        }
+    }
+
+    if let Some(provider) = tracer_provider
+        && let Err(error) = provider.shutdown()
+    {
+        log::error!("failed to flush traces: {error}");
+    }
+}
+
+async fn serve_tls(app: Router, app_state: AppState) {
+    let config = Arc::clone(&app_state.config);
+    let addr: SocketAddr = config.server.bind.parse().unwrap_or_else(|error| {
+        log::error!("failed to parse bind address: {error}");
+        process::exit(1)
+    });
+    let rustls_config = load_rustls_config(&config.tls)
         .await
         .unwrap_or_else(|error| {
-            log::error!("failed to start listener: {error}");
+            log::error!("failed to load tls certificate: {error}");
             process::exit(1)
         });
 
-    axum::serve(listener, app).await.unwrap_or_else(|error| {
-        log::error!("failed to start server: {error}");
-        std::process::exit(1)
-    })
+    tokio::spawn(watch_certificates(
+        rustls_config.clone(),
+        config.tls.clone(),
+    ));
+    if let Some(redirect_bind) = config.tls.redirect_bind.clone() {
+        tokio::spawn(serve_redirect(
+            redirect_bind,
+            https_port(&config.server.bind),
+            app_state.clone(),
+        ));
+    }
+
+    let handle = Handle::new();
+    let shutdown_handle = handle.clone();
+    tokio::spawn(async move {
+        shutdown_signal(app_state).await;
+        shutdown_handle.graceful_shutdown(None);
+    });
+
+    axum_server::bind_rustls(addr, rustls_config)
+        .handle(handle)
+        .serve(app.into_make_service())
+        .await
+        .unwrap_or_else(|error| {
+            log::error!("failed to start server: {error}");
+            std::process::exit(1)
+        });
 }
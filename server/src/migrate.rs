@@ -0,0 +1,61 @@
+use infra::db::migrations::{MigrateAction, migrate, run_migrations, verify_migrations};
+use shared::{config::MigrateOnStartup, types::DefaultError};
+use sqlx::PgPool;
+
+pub async fn prepare_schema(mode: MigrateOnStartup, pool: &PgPool) -> Result<(), DefaultError> {
+    match mode {
+        MigrateOnStartup::Apply => {
+            for migration in run_migrations(pool).await? {
+                log::info!(
+                    "applied migration {} {}",
+                    migration.version,
+                    migration.description
+                );
+            }
+        }
+        MigrateOnStartup::Verify => verify_migrations(pool).await?,
+        MigrateOnStartup::Off => (),
+    }
+
+    Ok(())
+}
+
+// `server migrate [run|pending|check]`
+pub async fn run_command(args: &[String], pool: &PgPool) -> Result<(), DefaultError> {
+    let action = match args.first() {
+        Some(name) => MigrateAction::parse(name)?,
+        None => MigrateAction::Run,
+    };
+    for line in migrate(action, pool).await? {
+        println!("{line}");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{prepare_schema, run_command};
+    use crate::test_utils::get_db_test_pool;
+    use shared::config::MigrateOnStartup;
+
+    #[tokio::test]
+    async fn test_prepare_schema() {
+        let pool = get_db_test_pool().await;
+
+        assert!(
+            prepare_schema(MigrateOnStartup::Verify, &pool)
+                .await
+                .is_ok()
+        );
+        assert!(prepare_schema(MigrateOnStartup::Apply, &pool).await.is_ok());
+    }
+    #[tokio::test]
+    async fn test_run_command() {
+        let pool = get_db_test_pool().await;
+
+        assert!(run_command(&["check".to_string()], &pool).await.is_ok());
+        assert!(run_command(&["pending".to_string()], &pool).await.is_ok());
+        assert!(run_command(&["rollback".to_string()], &pool).await.is_err());
+    }
+}
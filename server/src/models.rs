@@ -1,6 +1,14 @@
-use axum::{Json, http::StatusCode, response::IntoResponse};
-use serde::Serialize;
-use serde_json::json;
+use std::fmt::{self, Display};
+
+use axum::{
+    Json,
+    extract::ws::{CloseFrame, close_code},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tungstenite::error::CapacityError;
 
 pub struct ApiResponse;
 
@@ -13,6 +21,19 @@ impl ApiResponse {
         let body = Json(json!({ "success": success, "data": data }));
         (status_code, body)
     }
+    // extra top-level fields next to `data`, for responses that grew after clients relied on it
+    pub fn build_with<T: Serialize>(
+        success: bool,
+        data: T,
+        fields: Value,
+        status_code: StatusCode,
+    ) -> impl IntoResponse {
+        let mut body = json!({ "success": success, "data": data });
+        if let (Value::Object(body), Value::Object(fields)) = (&mut body, fields) {
+            body.extend(fields);
+        }
+        (status_code, Json(body))
+    }
 }
 
 #[derive(Serialize)]
@@ -31,3 +52,378 @@ impl RoomResponse {
         }
     }
 }
+
+#[derive(Serialize)]
+pub struct UnreadResponse {
+    room_uuid: String,
+    unread: i64,
+}
+
+impl UnreadResponse {
+    pub fn new(room_uuid: String, unread: i64) -> Self {
+        Self { room_uuid, unread }
+    }
+}
+
+#[derive(Serialize)]
+pub struct VersionResponse {
+    version: &'static str,
+    git_commit: &'static str,
+    build_timestamp: &'static str,
+    rustc_version: &'static str,
+    features: Vec<&'static str>,
+    migration_version: Option<i64>,
+}
+
+impl VersionResponse {
+    pub fn new(migration_version: Option<i64>) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            git_commit: env!("BUILD_GIT_COMMIT"),
+            build_timestamp: env!("BUILD_TIMESTAMP"),
+            rustc_version: env!("BUILD_RUSTC_VERSION"),
+            features: env!("BUILD_FEATURES")
+                .split(',')
+                .filter(|f| !f.is_empty())
+                .collect(),
+            migration_version,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct DependencyStatus {
+    pub healthy: bool,
+    latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl DependencyStatus {
+    pub fn new(healthy: bool, latency_ms: u64, error: Option<String>) -> Self {
+        Self {
+            healthy,
+            latency_ms,
+            error,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ReadinessResponse {
+    status: &'static str,
+    database: DependencyStatus,
+    redis: DependencyStatus,
+}
+
+impl ReadinessResponse {
+    pub fn new(database: DependencyStatus, redis: DependencyStatus) -> Self {
+        let status = if database.healthy && redis.healthy {
+            "ready"
+        } else {
+            "degraded"
+        };
+
+        Self {
+            status,
+            database,
+            redis,
+        }
+    }
+    pub fn is_ready(&self) -> bool {
+        self.status == "ready"
+    }
+}
+
+#[derive(Serialize)]
+pub struct AdminRoomResponse {
+    uuid: String,
+    created_at: String,
+    message_count: i64,
+    last_message_at: Option<String>,
+    online: usize,
+    members: Vec<String>,
+}
+
+impl AdminRoomResponse {
+    pub fn new(
+        uuid: String,
+        created_at: String,
+        message_count: i64,
+        last_message_at: Option<String>,
+        members: Vec<String>,
+    ) -> Self {
+        Self {
+            uuid,
+            created_at,
+            message_count,
+            last_message_at,
+            online: members.len(),
+            members,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct BanResponse {
+    kind: String,
+    value: String,
+    reason: Option<String>,
+    created_at: String,
+}
+
+impl BanResponse {
+    pub fn new(kind: String, value: String, reason: Option<String>, created_at: String) -> Self {
+        Self {
+            kind,
+            value,
+            reason,
+            created_at,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct RateLimitResponse {
+    subject: String,
+    remaining: Option<u16>,
+    ttl_secs: Option<i64>,
+    override_limit: Option<u16>,
+}
+
+impl RateLimitResponse {
+    pub fn new(
+        subject: String,
+        remaining: Option<u16>,
+        ttl_secs: Option<i64>,
+        override_limit: Option<u16>,
+    ) -> Self {
+        Self {
+            subject,
+            remaining,
+            ttl_secs,
+            override_limit,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct AuditLogResponse {
+    id: i64,
+    actor: String,
+    action: String,
+    room_uuid: Option<String>,
+    target: Option<String>,
+    detail: Option<String>,
+    created_at: String,
+}
+
+impl AuditLogResponse {
+    pub fn new(
+        id: i64,
+        actor: String,
+        action: String,
+        room_uuid: Option<String>,
+        target: Option<String>,
+        detail: Option<String>,
+        created_at: String,
+    ) -> Self {
+        Self {
+            id,
+            actor,
+            action,
+            room_uuid,
+            target,
+            detail,
+            created_at,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct WebhookResponse {
+    id: i32,
+    url: String,
+    room_uuid: Option<String>,
+    events: Vec<String>,
+    created_by: String,
+    created_at: String,
+    // only returned when the subscription is created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secret: Option<String>,
+}
+
+impl WebhookResponse {
+    pub fn new(
+        id: i32,
+        url: String,
+        room_uuid: Option<String>,
+        events: Vec<String>,
+        created_by: String,
+        created_at: String,
+        secret: Option<String>,
+    ) -> Self {
+        Self {
+            id,
+            url,
+            room_uuid,
+            events,
+            created_by,
+            created_at,
+            secret,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct DeadLetterResponse {
+    id: i64,
+    subscription_id: i32,
+    event: String,
+    payload: String,
+    attempts: i32,
+    last_error: Option<String>,
+    failed_at: String,
+}
+
+impl DeadLetterResponse {
+    pub fn new(
+        id: i64,
+        subscription_id: i32,
+        event: String,
+        payload: String,
+        attempts: i32,
+        last_error: Option<String>,
+        failed_at: String,
+    ) -> Self {
+        Self {
+            id,
+            subscription_id,
+            event,
+            payload,
+            attempts,
+            last_error,
+            failed_at,
+        }
+    }
+}
+
+// `from` and `to` are rfc 3339 timestamps, `to` is exclusive
+#[derive(Deserialize)]
+pub struct AuditQuery {
+    pub actor: Option<String>,
+    pub room: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub limit: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct BanRequest {
+    pub kind: String,
+    pub value: String,
+    pub reason: Option<String>,
+}
+
+// without a room the subscription covers every room, without events every event;
+// a secret is generated when none is given
+#[derive(Deserialize)]
+pub struct WebhookRequest {
+    pub url: String,
+    pub room: Option<String>,
+    pub events: Option<Vec<String>>,
+    pub secret: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DeadLetterQuery {
+    pub limit: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct RateLimitOverrideRequest {
+    pub limit: u16,
+    // the override is kept until removed when unset
+    pub ttl_secs: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct ConnectRoomQuery {
+    pub since: Option<i64>,
+    pub resume: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UnreadQuery {
+    pub resume: String,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientFrame {
+    Read {
+        message_id: i32,
+    },
+    Kick {
+        user: String,
+    },
+    // a duration of 0 lifts the mute
+    Mute {
+        user: String,
+        duration: u64,
+    },
+    Ban {
+        user: String,
+        reason: Option<String>,
+    },
+    SetRole {
+        user: String,
+        role: String,
+    },
+}
+
+// inbound frames the server refuses; either one ends the connection
+#[derive(Debug, PartialEq)]
+pub enum FrameError {
+    TooLarge { size: usize, max_size: usize },
+    BandwidthExceeded { bytes_per_sec: usize },
+}
+
+impl FrameError {
+    // None for read errors that aren't about the frame itself, like a dropped connection
+    pub fn from_recv_error(e: axum::Error) -> Option<Self> {
+        match e.into_inner().downcast_ref::<tungstenite::Error>() {
+            Some(tungstenite::Error::Capacity(CapacityError::MessageTooLong {
+                size,
+                max_size,
+            })) => Some(Self::TooLarge {
+                size: *size,
+                max_size: *max_size,
+            }),
+            _ => None,
+        }
+    }
+    pub fn close_frame(&self) -> CloseFrame {
+        let code = match self {
+            Self::TooLarge { .. } => close_code::SIZE,
+            Self::BandwidthExceeded { .. } => close_code::POLICY,
+        };
+        CloseFrame {
+            code,
+            reason: self.to_string().into(),
+        }
+    }
+}
+
+impl Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooLarge { max_size, .. } => {
+                write!(f, "message is larger than {} bytes", max_size)
+            }
+            Self::BandwidthExceeded { bytes_per_sec } => {
+                write!(f, "more than {} bytes per second", bytes_per_sec)
+            }
+        }
+    }
+}
@@ -2,10 +2,67 @@ use std::sync::Arc;
 
 use axum::http::HeaderMap;
 use redis::{AsyncCommands, Client};
-use shared::types::DefaultError;
+use shared::{
+    config::{RateLimitPolicy, RateLimitSubject},
+    models::AppState,
+    types::DefaultError,
+};
+use tracing::{Instrument, debug_span};
+use uuid::Uuid;
 
 use crate::utils::extract_request_ip;
 
+// keys are `rate_limiter:{policy}:{part}:{value}...`, e.g. `rate_limiter:chat:user:alice:room:{uuid}`
+pub const KEY_PREFIX: &str = "rate_limiter:";
+// an admin set limit for one subject, used instead of the policy limit
+pub const OVERRIDE_PREFIX: &str = "rate_limit_override:";
+
+// everything a request can be counted by
+pub struct Subject {
+    ip: String,
+    user: Option<String>,
+    room: Option<Uuid>,
+}
+
+impl Subject {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        Self {
+            ip: extract_request_ip(headers),
+            user: None,
+            room: None,
+        }
+    }
+    pub fn user(mut self, user: impl Into<String>) -> Self {
+        self.user = Some(user.into());
+        self
+    }
+    pub fn room(mut self, room: Uuid) -> Self {
+        self.room = Some(room);
+        self
+    }
+    // parts the request doesn't have are left out; with none left the ip is used
+    pub fn key(&self, policy_name: &str, parts: &[RateLimitSubject]) -> String {
+        let mut key = format!("{}{}", KEY_PREFIX, policy_name);
+        let mut found = false;
+        for part in parts {
+            let value = match part {
+                RateLimitSubject::Ip => Some(self.ip.clone()),
+                RateLimitSubject::User => self.user.clone(),
+                RateLimitSubject::Room => self.room.map(|room| room.to_string()),
+            };
+            if let Some(value) = value {
+                key.push_str(&format!(":{}:{}", part.as_str(), value));
+                found = true;
+            }
+        }
+        if !found {
+            key.push_str(&format!(":{}:{}", RateLimitSubject::Ip.as_str(), self.ip));
+        }
+
+        key
+    }
+}
+
 pub struct RateLimiter {
     key: String,
     limit: u16,
@@ -22,12 +79,17 @@ impl RateLimiter {
             redis_client,
         }
     }
+    #[tracing::instrument(name = "rate_limiter", level = "debug", skip_all, fields(key = %self.key))]
     async fn check_and_apply(&self) -> Result<bool, DefaultError> {
         let key = self.key.clone();
 
-        let mut conn = self.redis_client.get_multiplexed_async_connection().await?;
+        let mut conn = self
+            .redis_client
+            .get_multiplexed_async_connection()
+            .instrument(redis_span("CONNECT"))
+            .await?;
 
-        let get_cache: Option<String> = conn.get(&key).await?;
+        let get_cache: Option<String> = conn.get(&key).instrument(redis_span("GET")).await?;
         match get_cache {
             Some(raw_value) => {
                 let value: u16 = raw_value.parse()?;
@@ -35,36 +97,80 @@ impl RateLimiter {
                     return Ok(false);
                 }
 
-                conn.decr(&key, 1).await?
+                conn.decr(&key, 1).instrument(redis_span("DECR")).await?
             }
             None => {
-                conn.set_ex(&key, self.limit.to_string(), self.seconds)
+                let limit = match self.override_key() {
+                    Some(override_key) => conn
+                        .get::<_, Option<u16>>(override_key)
+                        .instrument(redis_span("GET"))
+                        .await?
+                        .unwrap_or(self.limit),
+                    None => self.limit,
+                };
+                if limit == 0 {
+                    return Ok(false);
+                }
+
+                conn.set_ex(&key, limit.to_string(), self.seconds)
+                    .instrument(redis_span("SETEX"))
                     .await?
             }
         }
 
         Ok(true)
     }
+    fn override_key(&self) -> Option<String> {
+        self.key
+            .strip_prefix(KEY_PREFIX)
+            .map(|subject| format!("{}{}", OVERRIDE_PREFIX, subject))
+    }
     pub async fn run(
-        headers: &HeaderMap,
-        limit: u16,
-        seconds: u64,
-        redis_client: Arc<Client>,
+        subject: &Subject,
+        policy_name: &str,
+        policy: &RateLimitPolicy,
+        app_state: &AppState,
     ) -> bool {
-        let key = format!("rate_limiter:{}", extract_request_ip(headers));
-        let rate_limiter = RateLimiter::new(key, limit, seconds, redis_client);
+        let key = subject.key(policy_name, &policy.subject);
+        let rate_limiter = RateLimiter::new(
+            key,
+            policy.limit,
+            policy.seconds,
+            Arc::clone(&app_state.redis_client),
+        );
 
-        rate_limiter.check_and_apply().await.unwrap_or(true)
+        match rate_limiter.check_and_apply().await {
+            Ok(true) => true,
+            Ok(false) => {
+                app_state.metrics.record_rate_limited(policy_name);
+                false
+            }
+            Err(e) => {
+                log::error!("failed to check rate limit: {e}");
+                app_state.metrics.record_redis_error();
+                true
+            }
+        }
     }
 }
 
+fn redis_span(operation: &'static str) -> tracing::Span {
+    debug_span!(
+        "redis.command",
+        db.system = "redis",
+        db.operation = operation
+    )
+}
+
 #[cfg(test)]
 mod tests {
-    use super::RateLimiter;
-    use crate::test_utils::get_redis_test_client;
+    use super::{RateLimiter, Subject};
+    use crate::test_utils::{get_redis_test_client, get_test_app_state};
     use axum::http::{HeaderMap, HeaderValue};
     use redis::AsyncCommands;
+    use shared::config::{RateLimitPolicy, RateLimitSubject};
     use std::sync::Arc;
+    use uuid::Uuid;
 
     #[tokio::test]
     async fn test_create_new_instance() {
@@ -162,15 +268,131 @@ mod tests {
         assert!(!result);
     }
     #[tokio::test]
-    async fn test_run() {
+    async fn test_check_and_apply_uses_override() {
         let redis_client = get_redis_test_client().await;
+        let rate_limiter = RateLimiter::new(
+            "rate_limiter:127.0.0.31".to_string(),
+            10,
+            10,
+            Arc::clone(&redis_client),
+        );
+
+        let mut conn = redis_client
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        conn.set::<_, _, ()>("rate_limit_override:127.0.0.31", "50")
+            .await
+            .unwrap();
+
+        assert!(rate_limiter.check_and_apply().await.unwrap());
+        assert_eq!(
+            conn.get::<_, Option<String>>(&rate_limiter.key)
+                .await
+                .unwrap()
+                .unwrap(),
+            "50"
+        );
+
+        conn.del::<_, ()>(&rate_limiter.key).await.unwrap();
+        conn.set::<_, _, ()>("rate_limit_override:127.0.0.31", "0")
+            .await
+            .unwrap();
+        assert!(!rate_limiter.check_and_apply().await.unwrap());
+
+        conn.del::<_, ()>("rate_limit_override:127.0.0.31")
+            .await
+            .unwrap();
+    }
+    #[tokio::test]
+    async fn test_run() {
+        let app_state = get_test_app_state().await;
         let mut headers = HeaderMap::new();
         headers.insert(
             "x-forwarded-for",
             HeaderValue::from_str("127.0.0.5").unwrap(),
         );
 
-        let result = RateLimiter::run(&headers, 10, 10, redis_client).await;
+        let result = RateLimiter::run(
+            &Subject::from_headers(&headers),
+            "test",
+            &RateLimitPolicy::new(10, 10),
+            &app_state,
+        )
+        .await;
         assert!(result)
     }
+    #[tokio::test]
+    async fn test_run_records_rejection() {
+        let app_state = get_test_app_state().await;
+        let mut conn = app_state
+            .redis_client
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        conn.set_ex::<_, _, ()>("rate_limiter:test:ip:127.0.0.27", "0", 10)
+            .await
+            .unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_str("127.0.0.27").unwrap(),
+        );
+
+        let result = RateLimiter::run(
+            &Subject::from_headers(&headers),
+            "test",
+            &RateLimitPolicy::new(10, 10),
+            &app_state,
+        )
+        .await;
+        assert!(!result);
+        assert_eq!(app_state.metrics.rate_limit_rejections("test"), 1);
+    }
+    #[test]
+    fn test_subject_key() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", HeaderValue::from_static("10.0.0.1"));
+        let room = Uuid::nil();
+        let subject = Subject::from_headers(&headers).user("alice").room(room);
+
+        assert_eq!(
+            subject.key("list", &[RateLimitSubject::Ip]),
+            "rate_limiter:list:ip:10.0.0.1"
+        );
+        assert_eq!(
+            subject.key("chat", &[RateLimitSubject::User, RateLimitSubject::Room]),
+            format!("rate_limiter:chat:user:alice:room:{}", room)
+        );
+        // a request without a user falls back to its ip
+        assert_eq!(
+            Subject::from_headers(&headers).key("list", &[RateLimitSubject::User]),
+            "rate_limiter:list:ip:10.0.0.1"
+        );
+    }
+    #[tokio::test]
+    async fn test_run_keeps_policies_apart() {
+        let app_state = get_test_app_state().await;
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", HeaderValue::from_static("127.0.0.42"));
+        let subject = Subject::from_headers(&headers).user(Uuid::new_v4().to_string());
+        let policy = RateLimitPolicy::new(1, 10);
+        let chat_policy = RateLimitPolicy::new(1, 10).with_subject(vec![RateLimitSubject::User]);
+
+        for _ in 0..2 {
+            assert!(RateLimiter::run(&subject, "list", &policy, &app_state).await);
+        }
+        assert!(!RateLimiter::run(&subject, "list", &policy, &app_state).await);
+        // the exhausted rest limit doesn't touch chat, which is counted per user
+        assert!(RateLimiter::run(&subject, "chat", &chat_policy, &app_state).await);
+
+        let mut conn = app_state
+            .redis_client
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        conn.del::<_, ()>("rate_limiter:list:ip:127.0.0.42")
+            .await
+            .unwrap();
+    }
 }
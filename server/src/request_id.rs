@@ -0,0 +1,63 @@
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use infra::logging::with_correlation_id;
+use shared::helpers::generate_uuid_v4;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+pub async fn assign_request_id(request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| is_valid_request_id(v))
+        .map(str::to_string)
+        .unwrap_or_else(|| generate_uuid_v4().to_string());
+
+    let mut response = with_correlation_id(request_id.clone(), next.run(request)).await;
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+
+    response
+}
+
+// ids coming from a proxy end up in log lines, so only accept short plain tokens
+fn is_valid_request_id(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= 64
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_valid_request_id;
+    use crate::test_utils::get_test_server;
+
+    #[test]
+    fn test_is_valid_request_id() {
+        assert!(is_valid_request_id("3f1c2a9e-7b7d-4a57-9d55-0d1f3b6a4e21"));
+        assert!(!is_valid_request_id(""));
+        assert!(!is_valid_request_id("bad id\n"));
+        assert!(!is_valid_request_id(&"a".repeat(65)));
+    }
+    #[tokio::test]
+    async fn test_assign_request_id() {
+        let server = get_test_server().await;
+
+        let response = server.get("/health").await;
+        assert_eq!(response.header("x-request-id").to_str().unwrap().len(), 36);
+
+        let response = server
+            .get("/health")
+            .add_header("x-request-id", "edge-1234")
+            .await;
+        assert_eq!(response.header("x-request-id"), "edge-1234");
+    }
+}
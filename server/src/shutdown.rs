@@ -0,0 +1,87 @@
+use std::{future, sync::atomic::Ordering, time::Duration};
+
+use shared::models::AppState;
+use tokio::{signal, time};
+
+pub async fn shutdown_signal(app_state: AppState) {
+    let ctrl_c = async {
+        if let Err(e) = signal::ctrl_c().await {
+            log::error!("failed to listen for ctrl-c: {e}");
+            future::pending::<()>().await
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                log::error!("failed to listen for sigterm: {e}");
+                future::pending::<()>().await
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    log::info!("shutdown signal received, closing websocket connections");
+    app_state.shutdown.send_replace(true);
+}
+
+pub async fn drain_connections(app_state: &AppState) -> bool {
+    let drain_timeout = Duration::from_secs(app_state.config.ws.shutdown_drain_secs);
+
+    let drained = time::timeout(drain_timeout, async {
+        while app_state.metrics.active_sockets.load(Ordering::Relaxed) > 0 {
+            time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await;
+
+    if drained.is_err() {
+        log::warn!(
+            "drain period elapsed with {} websocket connections still open",
+            app_state.metrics.active_sockets.load(Ordering::Relaxed)
+        );
+    }
+    drained.is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::drain_connections;
+    use crate::test_utils::get_test_app_state;
+    use shared::config::{Config, WsConfig};
+    use std::sync::{Arc, atomic::Ordering};
+
+    #[tokio::test]
+    async fn test_drain_connections_without_sockets() {
+        let app_state = get_test_app_state().await;
+
+        assert!(drain_connections(&app_state).await);
+    }
+    #[tokio::test]
+    async fn test_drain_connections_timeout() {
+        let mut app_state = get_test_app_state().await;
+        app_state.config = Arc::new(Config {
+            ws: WsConfig {
+                shutdown_drain_secs: 1,
+                ..WsConfig::default()
+            },
+            ..Config::default()
+        });
+        app_state
+            .metrics
+            .active_sockets
+            .fetch_add(1, Ordering::Relaxed);
+
+        assert!(!drain_connections(&app_state).await);
+    }
+}
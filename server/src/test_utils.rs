@@ -2,31 +2,33 @@ use crate::handlers::init_app;
 use axum_test::TestServer;
 use dotenvy::dotenv;
 use infra::cache::get_redis_client;
-use infra::db::create_pool;
+use infra::db::{create_pool, migrations::run_migrations};
 use redis::Client;
-use shared::models::AppState;
+use shared::{
+    config::{Config, DatabaseConfig},
+    models::AppState,
+};
 use sqlx::PgPool;
 use std::{collections::HashMap, sync::Arc};
 use tokio::sync::{Mutex, OnceCell};
 
 static REDIS_TEST_CLIENT: OnceCell<Arc<Client>> = OnceCell::const_new();
-static DB_TEST_POOL: OnceCell<Arc<PgPool>> = OnceCell::const_new();
-static TEST_SERVER: OnceCell<Arc<TestServer>> = OnceCell::const_new();
+static DB_TEST_READY: OnceCell<()> = OnceCell::const_new();
 
 pub async fn get_test_server() -> Arc<TestServer> {
-    TEST_SERVER
-        .get_or_init(|| async {
-            let redis_client = get_redis_test_client().await;
-            let db_pool = get_db_test_pool().await;
-            let channels = Arc::new(Mutex::new(HashMap::new()));
+    let app = init_app(get_test_app_state().await).await;
 
-            let app_state = AppState::new(db_pool, redis_client, channels);
-            let app = init_app(app_state).await;
+    Arc::new(TestServer::new(app).unwrap())
+}
 
-            Arc::new(TestServer::new(app).unwrap())
-        })
-        .await
-        .clone()
+pub async fn get_test_app_state() -> AppState {
+    let redis_client = get_redis_test_client().await;
+    let db_pool = get_db_test_pool().await;
+    let channels = Arc::new(Mutex::new(HashMap::new()));
+
+    let config = Arc::new(Config::default());
+
+    AppState::new(db_pool, redis_client, channels, config)
 }
 
 pub async fn get_redis_test_client() -> Arc<Client> {
@@ -39,12 +41,25 @@ pub async fn get_redis_test_client() -> Arc<Client> {
         .clone()
 }
 
+// every tokio test runs on its own runtime, so pool connections can't be shared
+// between tests; only the database creation and migrations are done once
 pub async fn get_db_test_pool() -> Arc<PgPool> {
-    DB_TEST_POOL
+    DB_TEST_READY
         .get_or_init(|| async {
             dotenv().ok();
-            Arc::new(create_pool().await.unwrap())
+            let pool = create_pool(&test_database_config()).await.unwrap();
+            run_migrations(&pool).await.unwrap();
+            pool.close().await;
         })
-        .await
-        .clone()
+        .await;
+
+    Arc::new(create_pool(&test_database_config()).await.unwrap())
+}
+
+pub fn test_database_config() -> DatabaseConfig {
+    DatabaseConfig {
+        min_connections: 0,
+        max_connections: 2,
+        ..DatabaseConfig::default()
+    }
 }
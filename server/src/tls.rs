@@ -0,0 +1,194 @@
+use std::{fs, net::SocketAddr, time::SystemTime};
+
+use axum::{
+    Router,
+    http::{HeaderMap, StatusCode, Uri, header::HOST},
+    response::{IntoResponse, Redirect, Response},
+};
+use axum_server::tls_rustls::RustlsConfig;
+use shared::{config::TlsConfig, models::AppState, types::DefaultError};
+use tokio::{net::TcpListener, time};
+
+type CertModified = Option<(SystemTime, SystemTime)>;
+
+pub async fn load_rustls_config(tls_config: &TlsConfig) -> Result<RustlsConfig, DefaultError> {
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    let rustls_config =
+        RustlsConfig::from_pem_file(&tls_config.cert_path, &tls_config.key_path).await?;
+
+    Ok(rustls_config)
+}
+
+pub async fn watch_certificates(rustls_config: RustlsConfig, tls_config: TlsConfig) {
+    let mut last_modified = cert_modified(&tls_config);
+    let mut interval = time::interval(time::Duration::from_secs(tls_config.reload_interval_secs));
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+        reload_if_changed(&rustls_config, &tls_config, &mut last_modified).await;
+    }
+}
+
+async fn reload_if_changed(
+    rustls_config: &RustlsConfig,
+    tls_config: &TlsConfig,
+    last_modified: &mut CertModified,
+) -> bool {
+    let modified = cert_modified(tls_config);
+    if modified.is_none() || modified == *last_modified {
+        return false;
+    }
+
+    match rustls_config
+        .reload_from_pem_file(&tls_config.cert_path, &tls_config.key_path)
+        .await
+    {
+        Ok(()) => {
+            log::info!("reloaded tls certificate from {}", tls_config.cert_path);
+            *last_modified = modified;
+            true
+        }
+        Err(e) => {
+            log::error!("failed to reload tls certificate: {e}");
+            false
+        }
+    }
+}
+
+fn cert_modified(tls_config: &TlsConfig) -> CertModified {
+    let modified = |path: &str| fs::metadata(path).and_then(|m| m.modified()).ok();
+
+    Some((
+        modified(&tls_config.cert_path)?,
+        modified(&tls_config.key_path)?,
+    ))
+}
+
+pub async fn serve_redirect(redirect_bind: String, https_port: u16, app_state: AppState) {
+    let listener = match TcpListener::bind(&redirect_bind).await {
+        Ok(l) => l,
+        Err(e) => {
+            log::error!("failed to start https redirect listener: {e}");
+            return;
+        }
+    };
+    let app = Router::new().fallback(move |headers: HeaderMap, uri: Uri| async move {
+        handle_redirect(headers, uri, https_port)
+    });
+
+    let mut shutdown_rx = app_state.shutdown.subscribe();
+    let result = axum::serve(listener, app)
+        .with_graceful_shutdown(async move {
+            let _ = shutdown_rx.wait_for(|stopping| *stopping).await;
+        })
+        .await;
+    if let Err(e) = result {
+        log::error!("https redirect listener failed: {e}");
+    }
+}
+
+fn handle_redirect(headers: HeaderMap, uri: Uri, https_port: u16) -> Response {
+    match headers
+        .get(HOST)
+        .and_then(|v| v.to_str().ok())
+        .map(|host| https_redirect_url(host, &uri, https_port))
+    {
+        Some(url) => Redirect::permanent(&url).into_response(),
+        None => StatusCode::BAD_REQUEST.into_response(),
+    }
+}
+
+fn https_redirect_url(host: &str, uri: &Uri, https_port: u16) -> String {
+    let hostname = match host.rsplit_once(':') {
+        Some((name, port))
+            if port.parse::<u16>().is_ok() && (!name.contains(':') || name.ends_with(']')) =>
+        {
+            name
+        }
+        _ => host,
+    };
+    let path = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+
+    if https_port == 443 {
+        format!("https://{}{}", hostname, path)
+    } else {
+        format!("https://{}:{}{}", hostname, https_port, path)
+    }
+}
+
+pub fn https_port(bind: &str) -> u16 {
+    bind.parse::<SocketAddr>().map(|a| a.port()).unwrap_or(443)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{https_redirect_url, load_rustls_config, reload_if_changed};
+    use axum::http::Uri;
+    use shared::{config::TlsConfig, helpers::generate_uuid_v4};
+    use std::{
+        fs::{self, File},
+        sync::Arc,
+        time::{Duration, SystemTime},
+    };
+
+    fn write_cert(tls_config: &TlsConfig, modified: SystemTime) {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        fs::write(&tls_config.cert_path, cert.cert.pem()).unwrap();
+        fs::write(&tls_config.key_path, cert.signing_key.serialize_pem()).unwrap();
+        for path in [&tls_config.cert_path, &tls_config.key_path] {
+            File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_https_redirect_url() {
+        let uri: Uri = "/room/list?page=2".parse().unwrap();
+
+        assert_eq!(
+            https_redirect_url("chat.example.com:80", &uri, 443),
+            "https://chat.example.com/room/list?page=2"
+        );
+        assert_eq!(
+            https_redirect_url("chat.example.com", &uri, 8443),
+            "https://chat.example.com:8443/room/list?page=2"
+        );
+        assert_eq!(
+            https_redirect_url("[::1]:8080", &"/".parse().unwrap(), 443),
+            "https://[::1]/"
+        );
+    }
+    #[tokio::test]
+    async fn test_reload_if_changed() {
+        let dir = std::env::temp_dir().join(format!("tls-{}", generate_uuid_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let tls_config = TlsConfig {
+            enabled: true,
+            cert_path: dir.join("cert.pem").to_string_lossy().into_owned(),
+            key_path: dir.join("key.pem").to_string_lossy().into_owned(),
+            ..TlsConfig::default()
+        };
+        let first_modified = SystemTime::now() - Duration::from_secs(60);
+        write_cert(&tls_config, first_modified);
+
+        let rustls_config = load_rustls_config(&tls_config).await.unwrap();
+        let initial = rustls_config.get_inner();
+        let mut last_modified = Some((first_modified, first_modified));
+
+        assert!(!reload_if_changed(&rustls_config, &tls_config, &mut last_modified).await);
+        assert!(Arc::ptr_eq(&initial, &rustls_config.get_inner()));
+
+        write_cert(&tls_config, SystemTime::now());
+
+        assert!(reload_if_changed(&rustls_config, &tls_config, &mut last_modified).await);
+        assert!(!Arc::ptr_eq(&initial, &rustls_config.get_inner()));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,4 +1,7 @@
-use axum::http::HeaderMap;
+use axum::http::{HeaderMap, header::HOST};
+use infra::db::audit::{AuditEntry, AuditLog};
+use shared::{config::Config, types::DefaultError};
+use sqlx::PgPool;
 
 pub fn extract_request_ip(headers: &HeaderMap) -> String {
     headers
@@ -8,10 +11,54 @@ pub fn extract_request_ip(headers: &HeaderMap) -> String {
         .unwrap_or("127.0.0.1".to_string())
 }
 
+pub fn public_base_url(config: &Config, headers: &HeaderMap) -> String {
+    let server_config = &config.server;
+    if let Some(public_url) = &server_config.public_url {
+        return public_url.trim_end_matches('/').to_string();
+    }
+
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|s| s.split(',').next())
+            .map(|s| s.trim().to_string())
+    };
+    let forwarded = |name: &str| {
+        if server_config.trust_forwarded_headers {
+            header(name)
+        } else {
+            None
+        }
+    };
+
+    // without a proxy saying otherwise, the native tls listener serves wss
+    let scheme = match forwarded("x-forwarded-proto").as_deref() {
+        Some("https") | Some("wss") => "wss",
+        None if config.tls.enabled => "wss",
+        _ => "ws",
+    };
+    let host = forwarded("x-forwarded-host")
+        .or_else(|| header(HOST.as_str()))
+        .unwrap_or_else(|| server_config.bind.clone());
+
+    format!("{}://{}", scheme, host)
+}
+
+// for actions that don't touch postgres themselves, like redis backed mutes
+pub async fn record_audit(db_pool: &PgPool, entry: AuditEntry) -> Result<(), DefaultError> {
+    let mut tx = db_pool.begin().await?;
+    AuditLog::record(&mut tx, entry).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
-    use super::extract_request_ip;
+    use super::{extract_request_ip, public_base_url};
     use axum::http::{HeaderMap, HeaderValue};
+    use shared::config::{Config, ServerConfig, TlsConfig};
 
     #[test]
     fn test_extract_request_ip_provide_needed_header() {
@@ -32,4 +79,75 @@ mod tests {
 
         assert_eq!(result, "127.0.0.1")
     }
+    #[test]
+    fn test_public_base_url_from_config() {
+        let server_config = ServerConfig {
+            public_url: Some("wss://chat.example.com/api/".to_string()),
+            ..ServerConfig::default()
+        };
+
+        let config = Config {
+            server: server_config,
+            ..Config::default()
+        };
+
+        let result = public_base_url(&config, &HeaderMap::new());
+
+        assert_eq!(result, "wss://chat.example.com/api")
+    }
+    #[test]
+    fn test_public_base_url_from_host_header() {
+        let mut headers = HeaderMap::new();
+        headers.insert("host", HeaderValue::from_static("chat.example.com"));
+        headers.insert("x-forwarded-proto", HeaderValue::from_static("https"));
+
+        let result = public_base_url(&Config::default(), &headers);
+
+        assert_eq!(result, "ws://chat.example.com")
+    }
+    #[test]
+    fn test_public_base_url_from_trusted_proxy() {
+        let server_config = ServerConfig {
+            trust_forwarded_headers: true,
+            ..ServerConfig::default()
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert("host", HeaderValue::from_static("10.0.0.5:3000"));
+        headers.insert("x-forwarded-proto", HeaderValue::from_static("https"));
+        headers.insert(
+            "x-forwarded-host",
+            HeaderValue::from_static("chat.example.com"),
+        );
+
+        let config = Config {
+            server: server_config,
+            ..Config::default()
+        };
+
+        let result = public_base_url(&config, &headers);
+
+        assert_eq!(result, "wss://chat.example.com")
+    }
+    #[test]
+    fn test_public_base_url_without_headers() {
+        let result = public_base_url(&Config::default(), &HeaderMap::new());
+
+        assert_eq!(result, "ws://0.0.0.0:3000")
+    }
+    #[test]
+    fn test_public_base_url_with_native_tls() {
+        let config = Config {
+            tls: TlsConfig {
+                enabled: true,
+                ..TlsConfig::default()
+            },
+            ..Config::default()
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert("host", HeaderValue::from_static("chat.example.com"));
+
+        let result = public_base_url(&config, &headers);
+
+        assert_eq!(result, "wss://chat.example.com")
+    }
 }
@@ -0,0 +1,397 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+use futures_util::future::join_all;
+use hmac::{Hmac, Mac};
+use infra::db::webhooks::WebhookDelivery;
+use reqwest::{Client, header::CONTENT_TYPE, redirect};
+use serde_json::{Value, json};
+use sha2::Sha256;
+use shared::{config::WebhookConfig, models::AppState, types::DefaultError};
+use tokio::{
+    task::JoinHandle,
+    time::{self, MissedTickBehavior},
+};
+use uuid::Uuid;
+
+pub const EVENT_HEADER: &str = "x-webhook-event";
+pub const DELIVERY_HEADER: &str = "x-webhook-delivery";
+pub const TIMESTAMP_HEADER: &str = "x-webhook-timestamp";
+pub const SIGNATURE_HEADER: &str = "x-webhook-signature";
+
+// receivers recompute it over `{timestamp}.{body}` with the subscription secret
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac takes keys of any length");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+pub fn payload(event: &str, room_uuid: Uuid, data: Value) -> String {
+    json!({
+        "event": event,
+        "room": room_uuid.to_string(),
+        "data": data,
+        "created_at": Utc::now().to_rfc3339(),
+    })
+    .to_string()
+}
+
+// for events that aren't stored together with other rows; failures only lose the webhook
+pub async fn notify(app_state: &AppState, event: &str, room_uuid: Uuid, data: Value) {
+    if !app_state.config.webhook.enabled {
+        return;
+    }
+
+    let result = async {
+        let mut tx = app_state.db_pool.begin().await?;
+        WebhookDelivery::enqueue(&mut tx, event, room_uuid, payload(event, room_uuid, data))
+            .await?;
+        tx.commit().await?;
+        Ok::<_, DefaultError>(())
+    }
+    .await;
+    if let Err(e) = result {
+        log::error!("failed to queue {event} webhooks: {e}");
+    }
+}
+
+pub fn spawn_worker(app_state: AppState) -> Option<JoinHandle<()>> {
+    let config = app_state.config.webhook.clone();
+    if !config.enabled {
+        return None;
+    }
+    let client = match http_client(&config) {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("failed to build webhook client: {e}");
+            return None;
+        }
+    };
+
+    Some(tokio::spawn(async move {
+        let mut interval = time::interval(Duration::from_millis(config.poll_interval_ms));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut shutdown_rx = app_state.shutdown.subscribe();
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = shutdown_rx.changed() => break,
+            }
+
+            // a full batch means more are probably due
+            loop {
+                match process_batch(&app_state, &client).await {
+                    Ok(claimed) if claimed == config.batch_size as usize => continue,
+                    Ok(_) => break,
+                    Err(e) => {
+                        log::error!("failed to process webhook deliveries: {e}");
+                        break;
+                    }
+                }
+            }
+        }
+    }))
+}
+
+pub fn http_client(config: &WebhookConfig) -> Result<Client, reqwest::Error> {
+    Client::builder()
+        .timeout(Duration::from_secs(config.timeout_secs))
+        .redirect(redirect::Policy::none())
+        .build()
+}
+
+// returns how many deliveries were claimed
+pub async fn process_batch(app_state: &AppState, client: &Client) -> Result<usize, DefaultError> {
+    let config = &app_state.config.webhook;
+    // long enough that a delivery still in flight isn't claimed by another worker
+    let lease_secs = config.timeout_secs as i32 * 2 + 5;
+    let deliveries = WebhookDelivery::claim(
+        Arc::clone(&app_state.db_pool),
+        config.batch_size,
+        lease_secs,
+    )
+    .await?;
+
+    let results = join_all(deliveries.iter().map(|delivery| deliver(client, delivery))).await;
+    for (delivery, result) in deliveries.iter().zip(results) {
+        if let Err(e) = record_outcome(app_state, delivery, result).await {
+            log::error!(
+                "failed to record webhook delivery {}: {e}",
+                delivery.get_id()
+            );
+        }
+    }
+
+    Ok(deliveries.len())
+}
+
+async fn deliver(client: &Client, delivery: &WebhookDelivery) -> Result<(), String> {
+    let body = delivery.get_payload();
+    let timestamp = Utc::now().timestamp();
+
+    let response = client
+        .post(delivery.get_url())
+        .header(CONTENT_TYPE, "application/json")
+        .header(EVENT_HEADER, delivery.get_event())
+        .header(DELIVERY_HEADER, delivery.get_id().to_string())
+        .header(TIMESTAMP_HEADER, timestamp.to_string())
+        .header(
+            SIGNATURE_HEADER,
+            sign(&delivery.get_secret(), timestamp, &body),
+        )
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("receiver answered {}", response.status()));
+    }
+
+    Ok(())
+}
+
+async fn record_outcome(
+    app_state: &AppState,
+    delivery: &WebhookDelivery,
+    result: Result<(), String>,
+) -> Result<(), DefaultError> {
+    let config = &app_state.config.webhook;
+    let mut tx = app_state.db_pool.begin().await?;
+
+    match result {
+        Ok(()) => WebhookDelivery::complete(&mut tx, delivery.get_id()).await?,
+        Err(error) => {
+            let attempts = delivery.get_attempts() + 1;
+            if attempts >= config.max_attempts {
+                log::warn!(
+                    "webhook delivery {} to {} failed {} times, moving it to dead letters: {}",
+                    delivery.get_id(),
+                    delivery.get_url(),
+                    attempts,
+                    error
+                );
+                WebhookDelivery::dead_letter(&mut tx, delivery.get_id(), error).await?;
+            } else {
+                let delay_secs = backoff_secs(config, attempts);
+                log::info!(
+                    "webhook delivery {} to {} failed, retrying in {}s: {}",
+                    delivery.get_id(),
+                    delivery.get_url(),
+                    delay_secs,
+                    error
+                );
+                WebhookDelivery::retry(&mut tx, delivery.get_id(), error, delay_secs).await?;
+            }
+        }
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// doubles from backoff_base_secs with every failed attempt, up to backoff_max_secs
+fn backoff_secs(config: &WebhookConfig, attempts: i32) -> i32 {
+    let exponent = (attempts - 1).clamp(0, 30) as u32;
+
+    config
+        .backoff_base_secs
+        .saturating_mul(2_i32.saturating_pow(exponent))
+        .min(config.backoff_max_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{
+        Router,
+        extract::State,
+        http::{HeaderMap, StatusCode},
+        routing::post,
+    };
+    use infra::db::webhooks::{WebhookDeadLetter, WebhookSubscription};
+    use shared::{
+        config::{Config, WebhookConfig},
+        models::AppState,
+    };
+    use tokio::net::TcpListener;
+    use uuid::Uuid;
+
+    use super::{
+        SIGNATURE_HEADER, TIMESTAMP_HEADER, backoff_secs, http_client, notify, process_batch, sign,
+    };
+    use crate::test_utils::get_test_app_state;
+
+    type Received = Arc<Mutex<Vec<(HeaderMap, String)>>>;
+
+    // answers every delivery with `status` and keeps what it got
+    async fn spawn_receiver(status: StatusCode) -> (String, Received) {
+        let received: Received = Arc::new(Mutex::new(Vec::new()));
+        let app =
+            Router::new()
+                .route(
+                    "/hook",
+                    post(
+                        move |State(received): State<Received>,
+                              headers: HeaderMap,
+                              body: String| async move {
+                            received.lock().unwrap().push((headers, body));
+                            status
+                        },
+                    ),
+                )
+                .with_state(Arc::clone(&received));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        (url, received)
+    }
+    async fn subscribe(app_state: &AppState, url: String, room_uuid: Uuid) -> i32 {
+        let mut tx = app_state.db_pool.begin().await.unwrap();
+        let subscription = WebhookSubscription::create(
+            &mut tx,
+            url,
+            "webhook-test-secret".to_string(),
+            Some(room_uuid),
+            vec![],
+            "test",
+        )
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+        subscription.get_id()
+    }
+    async fn unsubscribe(app_state: &AppState, id: i32) {
+        let mut tx = app_state.db_pool.begin().await.unwrap();
+        WebhookSubscription::delete(&mut tx, id, "test")
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+    }
+
+    #[test]
+    fn test_sign() {
+        assert_eq!(
+            sign(
+                "webhook-test-secret",
+                1700000000,
+                r#"{"event":"room.created"}"#
+            ),
+            "sha256=caa725ff7ed4dea7fb23922a3f5d8bb4f202ca8fb71f5507434654b3d309801f"
+        );
+    }
+    #[test]
+    fn test_backoff_secs() {
+        let config = WebhookConfig {
+            backoff_base_secs: 10,
+            backoff_max_secs: 60,
+            ..WebhookConfig::default()
+        };
+        assert_eq!(backoff_secs(&config, 1), 10);
+        assert_eq!(backoff_secs(&config, 2), 20);
+        assert_eq!(backoff_secs(&config, 3), 40);
+        assert_eq!(backoff_secs(&config, 4), 60);
+        assert_eq!(backoff_secs(&config, 40), 60);
+    }
+    #[tokio::test]
+    async fn test_delivers_signed_payload() {
+        let app_state = get_test_app_state().await;
+        let client = http_client(&app_state.config.webhook).unwrap();
+        let (url, received) = spawn_receiver(StatusCode::OK).await;
+        let room_uuid = Uuid::new_v4();
+        let subscription_id = subscribe(&app_state, url, room_uuid).await;
+
+        notify(
+            &app_state,
+            "room.created",
+            room_uuid,
+            serde_json::json!({ "uuid": room_uuid.to_string() }),
+        )
+        .await;
+        for _ in 0..50 {
+            process_batch(&app_state, &client).await.unwrap();
+            if !received.lock().unwrap().is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+
+        let (headers, body) = received.lock().unwrap().pop().unwrap();
+        let timestamp: i64 = headers[TIMESTAMP_HEADER].to_str().unwrap().parse().unwrap();
+        assert_eq!(
+            headers[SIGNATURE_HEADER].to_str().unwrap(),
+            sign("webhook-test-secret", timestamp, &body)
+        );
+        let payload: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(payload["event"], "room.created");
+        assert_eq!(payload["room"], room_uuid.to_string());
+
+        // the delivery is gone once the receiver accepted it
+        let pending: (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM webhook_delivery WHERE subscription_id = $1")
+                .bind(subscription_id)
+                .fetch_one(&*app_state.db_pool)
+                .await
+                .unwrap();
+        assert_eq!(pending.0, 0);
+
+        unsubscribe(&app_state, subscription_id).await;
+    }
+    #[tokio::test]
+    async fn test_failed_deliveries_become_dead_letters() {
+        let mut app_state = get_test_app_state().await;
+        app_state.config = Arc::new(Config {
+            webhook: WebhookConfig {
+                max_attempts: 2,
+                // retries come due right away
+                backoff_base_secs: 0,
+                ..WebhookConfig::default()
+            },
+            ..Config::default()
+        });
+        let client = http_client(&app_state.config.webhook).unwrap();
+        let (url, received) = spawn_receiver(StatusCode::INTERNAL_SERVER_ERROR).await;
+        let room_uuid = Uuid::new_v4();
+        let subscription_id = subscribe(&app_state, url, room_uuid).await;
+
+        notify(
+            &app_state,
+            "member.joined",
+            room_uuid,
+            serde_json::json!({}),
+        )
+        .await;
+
+        let mut dead_letter = None;
+        for _ in 0..50 {
+            process_batch(&app_state, &client).await.unwrap();
+            dead_letter = WebhookDeadLetter::read(None, Some(Arc::clone(&app_state.db_pool)), 1000)
+                .await
+                .unwrap()
+                .into_iter()
+                .find(|dead_letter| dead_letter.get_subscription_id() == subscription_id);
+            if dead_letter.is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+
+        let dead_letter = dead_letter.unwrap();
+        assert_eq!(dead_letter.get_attempts(), 2);
+        assert_eq!(dead_letter.get_event(), "member.joined");
+        assert!(
+            dead_letter
+                .get_last_error()
+                .unwrap()
+                .contains("500 Internal Server Error")
+        );
+        assert_eq!(received.lock().unwrap().len(), 2);
+
+        unsubscribe(&app_state, subscription_id).await;
+    }
+}
@@ -0,0 +1,886 @@
+use std::{env, fs, net::SocketAddr, path::Path};
+
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+use uuid::Uuid;
+
+use crate::types::DefaultError;
+
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+const ENV_PREFIX: &str = "CHAT__";
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub rate_limit: RateLimitConfig,
+    pub room: RoomConfig,
+    pub ws: WsConfig,
+    pub tls: TlsConfig,
+    pub log: LogConfig,
+    pub telemetry: TelemetryConfig,
+    pub admin: AdminConfig,
+    pub filter: FilterConfig,
+    pub spam: SpamConfig,
+    pub webhook: WebhookConfig,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: String,
+    pub public_url: Option<String>,
+    pub trust_forwarded_headers: bool,
+    pub readiness_timeout_ms: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: "0.0.0.0:3000".to_string(),
+            public_url: None,
+            trust_forwarded_headers: false,
+            readiness_timeout_ms: 1000,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, Deserialize, Serialize, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum MigrateOnStartup {
+    #[default]
+    Apply,
+    Verify,
+    Off,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub min_connections: u32,
+    pub max_connections: u32,
+    pub acquire_timeout_secs: u64,
+    pub idle_timeout_secs: u64,
+    pub migrate_on_startup: MigrateOnStartup,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            min_connections: 5,
+            max_connections: 30,
+            acquire_timeout_secs: 2,
+            idle_timeout_secs: 300,
+            migrate_on_startup: MigrateOnStartup::Apply,
+        }
+    }
+}
+
+// what a rate limit counter is kept per; several parts combine into one counter
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitSubject {
+    Ip,
+    User,
+    Room,
+}
+
+impl RateLimitSubject {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ip => "ip",
+            Self::User => "user",
+            Self::Room => "room",
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitPolicy {
+    pub limit: u16,
+    pub seconds: u64,
+    // left empty in the file, the policy keeps its default subject
+    #[serde(default)]
+    pub subject: Vec<RateLimitSubject>,
+}
+
+impl RateLimitPolicy {
+    pub fn new(limit: u16, seconds: u64) -> Self {
+        Self {
+            limit,
+            seconds,
+            subject: vec![RateLimitSubject::Ip],
+        }
+    }
+    pub fn with_subject(mut self, subject: Vec<RateLimitSubject>) -> Self {
+        self.subject = subject;
+        self
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub create_room: RateLimitPolicy,
+    pub list: RateLimitPolicy,
+    pub chat: RateLimitPolicy,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            create_room: RateLimitPolicy::new(10, 600),
+            list: RateLimitPolicy::new(10, 60),
+            // not per user: anonymous names are handed out per connection, so a reconnect
+            // would start a fresh counter
+            chat: RateLimitPolicy::new(10, 60)
+                .with_subject(vec![RateLimitSubject::Ip, RateLimitSubject::Room]),
+        }
+    }
+}
+
+impl RateLimitConfig {
+    fn fill_default_subjects(&mut self) {
+        let defaults = Self::default();
+        for (policy, default) in [
+            (&mut self.create_room, defaults.create_room),
+            (&mut self.list, defaults.list),
+            (&mut self.chat, defaults.chat),
+        ] {
+            if policy.subject.is_empty() {
+                policy.subject = default.subject;
+            }
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoomConfig {
+    pub ttl_secs: u64,
+    pub history_limit: i32,
+    pub resume_ttl_secs: u64,
+    pub max_mute_secs: u64,
+}
+
+impl Default for RoomConfig {
+    fn default() -> Self {
+        Self {
+            ttl_secs: 3600,
+            history_limit: 200,
+            resume_ttl_secs: 3600,
+            max_mute_secs: 86400,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WsConfig {
+    pub broadcast_capacity: usize,
+    pub ping_interval_secs: u64,
+    pub pong_timeout_secs: u64,
+    pub idle_timeout_secs: u64,
+    pub shutdown_drain_secs: u64,
+    pub reconnect_hint_secs: u64,
+    pub max_frame_bytes: usize,
+    pub max_message_bytes: usize,
+    pub bandwidth_bytes_per_sec: usize,
+    pub bandwidth_burst_bytes: usize,
+}
+
+impl Default for WsConfig {
+    fn default() -> Self {
+        Self {
+            broadcast_capacity: 100,
+            ping_interval_secs: 30,
+            pong_timeout_secs: 10,
+            idle_timeout_secs: 300,
+            shutdown_drain_secs: 10,
+            reconnect_hint_secs: 5,
+            max_frame_bytes: 16 * 1024,
+            max_message_bytes: 16 * 1024,
+            bandwidth_bytes_per_sec: 16 * 1024,
+            bandwidth_burst_bytes: 64 * 1024,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    pub enabled: bool,
+    pub cert_path: String,
+    pub key_path: String,
+    pub reload_interval_secs: u64,
+    pub redirect_bind: Option<String>,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            cert_path: "certs/cert.pem".to_string(),
+            key_path: "certs/key.pem".to_string(),
+            reload_interval_secs: 30,
+            redirect_bind: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, Deserialize, Serialize, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub level: String,
+    pub format: LogFormat,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            format: LogFormat::Text,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryConfig {
+    pub enabled: bool,
+    pub otlp_endpoint: String,
+    pub service_name: String,
+    pub sample_ratio: f64,
+    pub export_timeout_secs: u64,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            otlp_endpoint: "http://localhost:4318/v1/traces".to_string(),
+            service_name: "simple-chat".to_string(),
+            sample_ratio: 1.0,
+            export_timeout_secs: 10,
+        }
+    }
+}
+
+// the /admin api is disabled until a token is configured
+#[derive(Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    pub token: Option<String>,
+}
+
+#[derive(Clone, Copy, Default, Deserialize, Serialize, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum BlocklistAction {
+    #[default]
+    Mask,
+    Reject,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterConfig {
+    pub max_length: usize,
+    pub strip_control_chars: bool,
+    pub blocklist: Vec<String>,
+    pub blocklist_action: BlocklistAction,
+    pub block_links: bool,
+    pub link_blocked_rooms: Vec<String>,
+}
+
+impl Default for FilterConfig {
+    fn default() -> Self {
+        Self {
+            max_length: 2000,
+            strip_control_chars: true,
+            blocklist: Vec::new(),
+            blocklist_action: BlocklistAction::Mask,
+            block_links: false,
+            link_blocked_rooms: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpamConfig {
+    pub enabled: bool,
+    pub duplicate_window_secs: u64,
+    pub max_duplicates: usize,
+    pub similarity_threshold: f64,
+    pub burst_window_secs: u64,
+    pub room_burst_limit: usize,
+    pub global_burst_limit: usize,
+    pub new_account_secs: u64,
+    pub new_account_burst_limit: usize,
+    pub auto_mute_secs: u64,
+}
+
+impl Default for SpamConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            duplicate_window_secs: 60,
+            max_duplicates: 3,
+            similarity_threshold: 0.9,
+            burst_window_secs: 10,
+            room_burst_limit: 8,
+            global_burst_limit: 15,
+            new_account_secs: 60,
+            new_account_burst_limit: 3,
+            auto_mute_secs: 300,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub enabled: bool,
+    pub poll_interval_ms: u64,
+    pub batch_size: i32,
+    pub timeout_secs: u64,
+    pub max_attempts: i32,
+    pub backoff_base_secs: i32,
+    pub backoff_max_secs: i32,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            poll_interval_ms: 1000,
+            batch_size: 20,
+            timeout_secs: 10,
+            max_attempts: 8,
+            backoff_base_secs: 10,
+            backoff_max_secs: 3600,
+        }
+    }
+}
+
+impl Config {
+    pub fn load() -> Result<Self, DefaultError> {
+        let path = env::var("CONFIG_PATH").ok();
+        let raw = match &path {
+            Some(p) => fs::read_to_string(p)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                fs::read_to_string(DEFAULT_CONFIG_PATH)?
+            }
+            None => String::new(),
+        };
+
+        Self::parse(&raw, env::vars())
+    }
+    pub fn parse(
+        raw: &str,
+        vars: impl Iterator<Item = (String, String)>,
+    ) -> Result<Self, DefaultError> {
+        let mut table: Table = toml::from_str(raw)?;
+        apply_env_overrides(&mut table, vars)?;
+        fill_policy_defaults(&mut table)?;
+
+        let mut config: Config = table.try_into()?;
+        config.rate_limit.fill_default_subjects();
+        config.validate()?;
+
+        Ok(config)
+    }
+    pub fn validate(&self) -> Result<(), DefaultError> {
+        self.server
+            .bind
+            .parse::<SocketAddr>()
+            .map_err(|e| format!("server.bind is not a valid socket address: {e}"))?;
+        if let Some(public_url) = &self.server.public_url
+            && !public_url.starts_with("ws://")
+            && !public_url.starts_with("wss://")
+        {
+            return Err("server.public_url must start with ws:// or wss://".into());
+        }
+
+        if self.server.readiness_timeout_ms == 0 {
+            return Err("server.readiness_timeout_ms must be greater than zero".into());
+        }
+
+        if self.database.max_connections == 0 {
+            return Err("database.max_connections must be greater than zero".into());
+        }
+        if self.database.min_connections > self.database.max_connections {
+            return Err("database.min_connections must not exceed max_connections".into());
+        }
+        for (name, policy) in [
+            ("create_room", &self.rate_limit.create_room),
+            ("list", &self.rate_limit.list),
+            ("chat", &self.rate_limit.chat),
+        ] {
+            if policy.seconds == 0 {
+                return Err(format!("rate_limit.{name}.seconds must be greater than zero").into());
+            }
+        }
+        if self.room.ttl_secs == 0 || self.room.resume_ttl_secs == 0 {
+            return Err("room.ttl_secs and room.resume_ttl_secs must be greater than zero".into());
+        }
+        if self.room.max_mute_secs == 0 {
+            return Err("room.max_mute_secs must be greater than zero".into());
+        }
+        if self.room.history_limit <= 0 {
+            return Err("room.history_limit must be greater than zero".into());
+        }
+        if self.ws.broadcast_capacity == 0 {
+            return Err("ws.broadcast_capacity must be greater than zero".into());
+        }
+        if self.ws.ping_interval_secs == 0
+            || self.ws.pong_timeout_secs == 0
+            || self.ws.idle_timeout_secs == 0
+        {
+            return Err("ws ping, pong and idle timeouts must be greater than zero".into());
+        }
+        if self.ws.max_frame_bytes == 0 || self.ws.bandwidth_bytes_per_sec == 0 {
+            return Err(
+                "ws.max_frame_bytes and ws.bandwidth_bytes_per_sec must be greater than zero"
+                    .into(),
+            );
+        }
+        if self.ws.max_frame_bytes > self.ws.max_message_bytes {
+            return Err("ws.max_frame_bytes must not exceed ws.max_message_bytes".into());
+        }
+        // otherwise a message at the size limit could never be accepted
+        if self.ws.max_message_bytes > self.ws.bandwidth_burst_bytes {
+            return Err("ws.max_message_bytes must not exceed ws.bandwidth_burst_bytes".into());
+        }
+        if self.log.level.trim().is_empty() {
+            return Err("log.level must not be empty".into());
+        }
+        if !(0.0..=1.0).contains(&self.telemetry.sample_ratio) {
+            return Err("telemetry.sample_ratio must be between 0 and 1".into());
+        }
+        if self.filter.max_length == 0 {
+            return Err("filter.max_length must be greater than zero".into());
+        }
+        for room in &self.filter.link_blocked_rooms {
+            Uuid::parse_str(room).map_err(|e| {
+                format!("filter.link_blocked_rooms has an invalid uuid {room}: {e}")
+            })?;
+        }
+        if self.spam.enabled {
+            if !(0.0..=1.0).contains(&self.spam.similarity_threshold) {
+                return Err("spam.similarity_threshold must be between 0 and 1".into());
+            }
+            if self.spam.duplicate_window_secs == 0
+                || self.spam.burst_window_secs == 0
+                || self.spam.max_duplicates == 0
+                || self.spam.room_burst_limit == 0
+                || self.spam.global_burst_limit == 0
+                || self.spam.new_account_burst_limit == 0
+            {
+                return Err("spam windows and limits must be greater than zero".into());
+            }
+            if self.spam.auto_mute_secs > self.room.max_mute_secs {
+                return Err("spam.auto_mute_secs must not exceed room.max_mute_secs".into());
+            }
+        }
+        if self.webhook.enabled {
+            if self.webhook.poll_interval_ms == 0
+                || self.webhook.batch_size <= 0
+                || self.webhook.timeout_secs == 0
+                || self.webhook.max_attempts <= 0
+                || self.webhook.backoff_base_secs <= 0
+            {
+                return Err(
+                    "webhook intervals, timeouts and limits must be greater than zero".into(),
+                );
+            }
+            if self.webhook.backoff_base_secs > self.webhook.backoff_max_secs {
+                return Err("webhook.backoff_base_secs must not exceed backoff_max_secs".into());
+            }
+        }
+        if let Some(token) = &self.admin.token
+            && token.len() < 16
+        {
+            return Err("admin.token must be at least 16 characters".into());
+        }
+        if self.tls.enabled {
+            if self.tls.cert_path.is_empty() || self.tls.key_path.is_empty() {
+                return Err("tls.cert_path and tls.key_path must be set".into());
+            }
+            if self.tls.reload_interval_secs == 0 {
+                return Err("tls.reload_interval_secs must be greater than zero".into());
+            }
+            if let Some(redirect_bind) = &self.tls.redirect_bind {
+                redirect_bind
+                    .parse::<SocketAddr>()
+                    .map_err(|e| format!("tls.redirect_bind is not a valid socket address: {e}"))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// `CHAT__WS__BROADCAST_CAPACITY=50` overrides `broadcast_capacity` in the `[ws]` table
+fn apply_env_overrides(
+    table: &mut Table,
+    vars: impl Iterator<Item = (String, String)>,
+) -> Result<(), DefaultError> {
+    let defaults = Table::try_from(Config::default())?;
+
+    for (name, value) in vars {
+        let Some(path) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        let keys: Vec<String> = path.split("__").map(|k| k.to_lowercase()).collect();
+        let Some((last, parents)) = keys.split_last() else {
+            continue;
+        };
+
+        let mut current = &mut *table;
+        for key in parents {
+            current = current
+                .entry(key.clone())
+                .or_insert_with(|| Value::Table(Table::new()))
+                .as_table_mut()
+                .ok_or_else(|| format!("{name} does not point into a config table"))?;
+        }
+        let value = parse_env_value(&name, &value, default_value(&defaults, &keys))?;
+        current.insert(last.clone(), value);
+    }
+
+    Ok(())
+}
+
+// a policy given only some of its fields takes the rest from that policy's own defaults,
+// they differ per policy so serde's field defaults can't fill them
+fn fill_policy_defaults(table: &mut Table) -> Result<(), DefaultError> {
+    let Some(Value::Table(rate_limit)) = table.get_mut("rate_limit") else {
+        return Ok(());
+    };
+    let defaults = Table::try_from(RateLimitConfig::default())?;
+
+    for (name, policy) in rate_limit.iter_mut() {
+        let (Value::Table(policy), Some(Value::Table(default))) = (policy, defaults.get(name))
+        else {
+            continue;
+        };
+        for (key, value) in default {
+            policy.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+    }
+
+    Ok(())
+}
+
+fn default_value<'a>(defaults: &'a Table, keys: &[String]) -> Option<&'a Value> {
+    let (last, parents) = keys.split_last()?;
+    let mut current = defaults;
+    for key in parents {
+        current = current.get(key)?.as_table()?;
+    }
+    current.get(last)
+}
+
+// the field's default value tells its type; fields without one, like unset options, take
+// the raw string, and lists are comma separated
+fn parse_env_value(
+    name: &str,
+    value: &str,
+    default: Option<&Value>,
+) -> Result<Value, DefaultError> {
+    let invalid = |kind: &str| format!("{name} must be {kind}");
+
+    let parsed = match default {
+        Some(Value::Integer(_)) => {
+            Value::Integer(value.trim().parse().map_err(|_| invalid("an integer"))?)
+        }
+        Some(Value::Float(_)) => {
+            Value::Float(value.trim().parse().map_err(|_| invalid("a number"))?)
+        }
+        Some(Value::Boolean(_)) => {
+            Value::Boolean(value.trim().parse().map_err(|_| invalid("true or false"))?)
+        }
+        Some(Value::Array(items)) => Value::Array(
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| parse_env_value(name, item, items.first()))
+                .collect::<Result<_, _>>()?,
+        ),
+        _ => Value::String(value.to_string()),
+    };
+
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BlocklistAction, Config, LogFormat, RateLimitSubject};
+
+    fn vars(pairs: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn test_parse_empty_config_uses_defaults() {
+        let config = Config::parse("", vars(&[])).unwrap();
+
+        assert_eq!(config.server.bind, "0.0.0.0:3000");
+        assert_eq!(config.database.max_connections, 30);
+        assert_eq!(config.rate_limit.create_room.seconds, 600);
+        assert_eq!(config.room.history_limit, 200);
+        assert_eq!(config.ws.broadcast_capacity, 100);
+    }
+    #[test]
+    fn test_parse_file_values() {
+        let raw = r#"
+            [server]
+            bind = "127.0.0.1:8080"
+
+            [rate_limit.chat]
+            limit = 20
+            seconds = 30
+        "#;
+        let config = Config::parse(raw, vars(&[])).unwrap();
+
+        assert_eq!(config.server.bind, "127.0.0.1:8080");
+        assert_eq!(config.rate_limit.chat.limit, 20);
+        assert_eq!(config.rate_limit.list.limit, 10);
+    }
+    #[test]
+    fn test_parse_env_overrides_file() {
+        let raw = "[ws]\nbroadcast_capacity = 50\n";
+        let config = Config::parse(
+            raw,
+            vars(&[
+                ("CHAT__WS__BROADCAST_CAPACITY", "75"),
+                ("CHAT__RATE_LIMIT__CHAT__LIMIT", "3"),
+                ("CHAT__RATE_LIMIT__CHAT__SECONDS", "5"),
+                ("UNRELATED", "1"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(config.ws.broadcast_capacity, 75);
+        assert_eq!(config.rate_limit.chat.limit, 3);
+        assert_eq!(config.rate_limit.chat.seconds, 5);
+    }
+    #[test]
+    fn test_parse_env_overrides_by_field_type() {
+        let config = Config::parse(
+            "",
+            vars(&[
+                ("CHAT__ADMIN__TOKEN", "1234567890123456"),
+                ("CHAT__SERVER__PUBLIC_URL", "wss://chat.example.com"),
+                ("CHAT__SPAM__SIMILARITY_THRESHOLD", "1"),
+                ("CHAT__SPAM__ENABLED", "false"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(config.admin.token.as_deref(), Some("1234567890123456"));
+        assert_eq!(
+            config.server.public_url.as_deref(),
+            Some("wss://chat.example.com")
+        );
+        assert_eq!(config.spam.similarity_threshold, 1.0);
+        assert!(!config.spam.enabled);
+
+        assert!(Config::parse("", vars(&[("CHAT__WS__BROADCAST_CAPACITY", "many")])).is_err());
+    }
+    #[test]
+    fn test_parse_env_overrides_lists() {
+        let config = Config::parse(
+            "",
+            vars(&[
+                ("CHAT__FILTER__BLOCKLIST", "darn, heck,"),
+                ("CHAT__RATE_LIMIT__LIST__LIMIT", "5"),
+                ("CHAT__RATE_LIMIT__LIST__SECONDS", "10"),
+                ("CHAT__RATE_LIMIT__LIST__SUBJECT", "ip,user"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(config.filter.blocklist, vec!["darn", "heck"]);
+        assert_eq!(
+            config.rate_limit.list.subject,
+            vec![RateLimitSubject::Ip, RateLimitSubject::User]
+        );
+    }
+    #[test]
+    fn test_parse_partial_rate_limit_policy() {
+        let raw = "[rate_limit.chat]\nlimit = 20\n\n[rate_limit.create_room]\nseconds = 30\n";
+        let config = Config::parse(raw, vars(&[])).unwrap();
+
+        assert_eq!(config.rate_limit.chat.limit, 20);
+        assert_eq!(config.rate_limit.chat.seconds, 60);
+        assert_eq!(
+            config.rate_limit.chat.subject,
+            vec![RateLimitSubject::Ip, RateLimitSubject::Room]
+        );
+        assert_eq!(config.rate_limit.create_room.limit, 10);
+        assert_eq!(config.rate_limit.create_room.seconds, 30);
+
+        let config = Config::parse(
+            "",
+            vars(&[
+                ("CHAT__RATE_LIMIT__CHAT__LIMIT", "20"),
+                ("CHAT__RATE_LIMIT__CREATE_ROOM__SECONDS", "30"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(config.rate_limit.chat.limit, 20);
+        assert_eq!(config.rate_limit.chat.seconds, 60);
+        assert_eq!(config.rate_limit.create_room.limit, 10);
+        assert_eq!(config.rate_limit.create_room.seconds, 30);
+    }
+    #[test]
+    fn test_parse_rate_limit_subject() {
+        let config = Config::parse(
+            "[rate_limit.chat]\nlimit = 5\nseconds = 10\n\n[rate_limit.list]\nlimit = 5\nseconds = 10\nsubject = [\"ip\", \"user\"]\n",
+            vars(&[]),
+        )
+        .unwrap();
+        assert_eq!(
+            config.rate_limit.chat.subject,
+            vec![RateLimitSubject::Ip, RateLimitSubject::Room]
+        );
+        assert_eq!(
+            config.rate_limit.list.subject,
+            vec![RateLimitSubject::Ip, RateLimitSubject::User]
+        );
+        assert_eq!(
+            config.rate_limit.create_room.subject,
+            vec![RateLimitSubject::Ip]
+        );
+
+        assert!(
+            Config::parse(
+                "[rate_limit.list]\nlimit = 5\nseconds = 10\nsubject = [\"device\"]\n",
+                vars(&[])
+            )
+            .is_err()
+        );
+    }
+    #[test]
+    fn test_parse_log_format() {
+        let config = Config::parse("[log]\nformat = \"json\"\n", vars(&[])).unwrap();
+        assert_eq!(config.log.format, LogFormat::Json);
+
+        assert!(Config::parse("[log]\nformat = \"xml\"\n", vars(&[])).is_err());
+    }
+    #[test]
+    fn test_parse_admin_token() {
+        let config = Config::parse("", vars(&[])).unwrap();
+        assert!(config.admin.token.is_none());
+
+        let config = Config::parse(
+            "",
+            vars(&[("CHAT__ADMIN__TOKEN", "change-me-to-something-long")]),
+        )
+        .unwrap();
+        assert_eq!(
+            config.admin.token.as_deref(),
+            Some("change-me-to-something-long")
+        );
+
+        assert!(Config::parse("[admin]\ntoken = \"short\"\n", vars(&[])).is_err());
+    }
+    #[test]
+    fn test_parse_filter() {
+        let config = Config::parse(
+            "[filter]\nblocklist = [\"darn\"]\nblocklist_action = \"reject\"\nlink_blocked_rooms = [\"6f1c2a8e-5d4b-4c1a-9e7f-2b3c4d5e6f70\"]\n",
+            vars(&[]),
+        )
+        .unwrap();
+        assert_eq!(config.filter.blocklist, vec!["darn".to_string()]);
+        assert_eq!(config.filter.blocklist_action, BlocklistAction::Reject);
+        assert_eq!(config.filter.max_length, 2000);
+
+        assert!(Config::parse("[filter]\nlink_blocked_rooms = [\"lobby\"]\n", vars(&[])).is_err());
+        assert!(Config::parse("[filter]\nmax_length = 0\n", vars(&[])).is_err());
+    }
+    #[test]
+    fn test_parse_spam() {
+        let config = Config::parse(
+            "[spam]\nmax_duplicates = 5\n",
+            vars(&[("CHAT__SPAM__AUTO_MUTE_SECS", "0")]),
+        )
+        .unwrap();
+        assert_eq!(config.spam.max_duplicates, 5);
+        assert_eq!(config.spam.auto_mute_secs, 0);
+
+        assert!(Config::parse("[spam]\nsimilarity_threshold = 1.5\n", vars(&[])).is_err());
+        assert!(Config::parse("[spam]\nroom_burst_limit = 0\n", vars(&[])).is_err());
+        assert!(
+            Config::parse("[spam]\nenabled = false\nroom_burst_limit = 0\n", vars(&[])).is_ok()
+        );
+    }
+    #[test]
+    fn test_parse_webhook() {
+        let config = Config::parse("[webhook]\nmax_attempts = 3\n", vars(&[])).unwrap();
+        assert_eq!(config.webhook.max_attempts, 3);
+        assert_eq!(config.webhook.backoff_base_secs, 10);
+
+        assert!(Config::parse("[webhook]\nbatch_size = 0\n", vars(&[])).is_err());
+        assert!(
+            Config::parse(
+                "[webhook]\nbackoff_base_secs = 60\nbackoff_max_secs = 30\n",
+                vars(&[])
+            )
+            .is_err()
+        );
+    }
+    #[test]
+    fn test_parse_rejects_unknown_field() {
+        assert!(Config::parse("[ws]\nbroadcast_capacty = 1\n", vars(&[])).is_err());
+    }
+    #[test]
+    fn test_parse_rejects_invalid_values() {
+        assert!(Config::parse("[ws]\nbroadcast_capacity = 0\n", vars(&[])).is_err());
+        assert!(Config::parse("[server]\nbind = \"nowhere\"\n", vars(&[])).is_err());
+        assert!(
+            Config::parse(
+                "[server]\npublic_url = \"https://chat.example.com\"\n",
+                vars(&[])
+            )
+            .is_err()
+        );
+        assert!(
+            Config::parse(
+                "[tls]\nenabled = true\nredirect_bind = \"port 80\"\n",
+                vars(&[])
+            )
+            .is_err()
+        );
+        assert!(
+            Config::parse(
+                "[database]\nmin_connections = 10\nmax_connections = 5\n",
+                vars(&[])
+            )
+            .is_err()
+        );
+        assert!(
+            Config::parse(
+                "[ws]\nmax_frame_bytes = 2048\nmax_message_bytes = 1024\n",
+                vars(&[])
+            )
+            .is_err()
+        );
+        assert!(Config::parse("[ws]\nbandwidth_burst_bytes = 1024\n", vars(&[])).is_err());
+    }
+}
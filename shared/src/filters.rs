@@ -0,0 +1,253 @@
+use std::collections::HashSet;
+
+use uuid::Uuid;
+
+use crate::config::{BlocklistAction, FilterConfig};
+
+// returns the message to pass on, possibly rewritten, or the reason it was refused
+pub trait MessageFilter: Send + Sync {
+    fn apply(&self, message: String, room_uuid: Uuid) -> Result<String, String>;
+}
+
+pub struct FilterPipeline {
+    filters: Vec<Box<dyn MessageFilter>>,
+}
+
+impl FilterPipeline {
+    pub fn new(filters: Vec<Box<dyn MessageFilter>>) -> Self {
+        Self { filters }
+    }
+    pub fn from_config(config: &FilterConfig) -> Self {
+        let mut filters: Vec<Box<dyn MessageFilter>> = Vec::new();
+        if config.strip_control_chars {
+            filters.push(Box::new(ControlCharFilter));
+        }
+        filters.push(Box::new(MaxLengthFilter::new(config.max_length)));
+        if !config.blocklist.is_empty() {
+            filters.push(Box::new(BlocklistFilter::new(
+                &config.blocklist,
+                config.blocklist_action,
+            )));
+        }
+        if config.block_links || !config.link_blocked_rooms.is_empty() {
+            filters.push(Box::new(LinkFilter::new(
+                config.block_links,
+                // validated with the config
+                config
+                    .link_blocked_rooms
+                    .iter()
+                    .filter_map(|room| Uuid::parse_str(room).ok())
+                    .collect(),
+            )));
+        }
+
+        Self::new(filters)
+    }
+    pub fn apply(&self, message: String, room_uuid: Uuid) -> Result<String, String> {
+        self.filters
+            .iter()
+            .try_fold(message, |message, filter| filter.apply(message, room_uuid))
+    }
+}
+
+// keeps newlines and tabs
+pub struct ControlCharFilter;
+
+impl MessageFilter for ControlCharFilter {
+    fn apply(&self, message: String, _room_uuid: Uuid) -> Result<String, String> {
+        let stripped: String = message
+            .chars()
+            .filter(|c| !c.is_control() || *c == '\n' || *c == '\t')
+            .collect();
+        if stripped.trim().is_empty() {
+            return Err("message is empty".to_string());
+        }
+
+        Ok(stripped)
+    }
+}
+
+pub struct MaxLengthFilter {
+    max_length: usize,
+}
+
+impl MaxLengthFilter {
+    pub fn new(max_length: usize) -> Self {
+        Self { max_length }
+    }
+}
+
+impl MessageFilter for MaxLengthFilter {
+    fn apply(&self, message: String, _room_uuid: Uuid) -> Result<String, String> {
+        if message.chars().count() > self.max_length {
+            return Err(format!(
+                "message is longer than {} characters",
+                self.max_length
+            ));
+        }
+
+        Ok(message)
+    }
+}
+
+pub struct BlocklistFilter {
+    words: HashSet<String>,
+    action: BlocklistAction,
+}
+
+impl BlocklistFilter {
+    pub fn new(words: &[String], action: BlocklistAction) -> Self {
+        Self {
+            words: words.iter().map(|word| word.to_lowercase()).collect(),
+            action,
+        }
+    }
+}
+
+impl MessageFilter for BlocklistFilter {
+    fn apply(&self, message: String, _room_uuid: Uuid) -> Result<String, String> {
+        let mut filtered = String::with_capacity(message.len());
+        let mut word = String::new();
+        let mut blocked = false;
+
+        // a trailing separator flushes the last word
+        for c in message.chars().chain(std::iter::once(' ')) {
+            if c.is_alphanumeric() {
+                word.push(c);
+                continue;
+            }
+            if !word.is_empty() {
+                if self.words.contains(&word.to_lowercase()) {
+                    blocked = true;
+                    filtered.extend(word.chars().map(|_| '*'));
+                } else {
+                    filtered.push_str(&word);
+                }
+                word.clear();
+            }
+            filtered.push(c);
+        }
+        filtered.pop();
+
+        match (blocked, self.action) {
+            (true, BlocklistAction::Reject) => Err("message contains a blocked word".to_string()),
+            (true, BlocklistAction::Mask) => Ok(filtered),
+            (false, _) => Ok(message),
+        }
+    }
+}
+
+pub struct LinkFilter {
+    all_rooms: bool,
+    rooms: HashSet<Uuid>,
+}
+
+impl LinkFilter {
+    pub fn new(all_rooms: bool, rooms: HashSet<Uuid>) -> Self {
+        Self { all_rooms, rooms }
+    }
+}
+
+impl MessageFilter for LinkFilter {
+    fn apply(&self, message: String, room_uuid: Uuid) -> Result<String, String> {
+        if !self.all_rooms && !self.rooms.contains(&room_uuid) {
+            return Ok(message);
+        }
+
+        let lowercase = message.to_lowercase();
+        if ["http://", "https://", "www."]
+            .iter()
+            .any(|prefix| lowercase.contains(prefix))
+        {
+            return Err("links are not allowed in this room".to_string());
+        }
+
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use uuid::Uuid;
+
+    use super::{
+        BlocklistFilter, ControlCharFilter, FilterPipeline, LinkFilter, MaxLengthFilter,
+        MessageFilter,
+    };
+    use crate::config::{BlocklistAction, FilterConfig};
+
+    #[test]
+    fn test_control_char_filter() {
+        let room = Uuid::nil();
+
+        assert_eq!(
+            ControlCharFilter.apply("hi\u{7}\u{1b}[31m\nthere".to_string(), room),
+            Ok("hi[31m\nthere".to_string())
+        );
+        assert!(
+            ControlCharFilter
+                .apply("\u{0}\u{8}".to_string(), room)
+                .is_err()
+        );
+    }
+    #[test]
+    fn test_max_length_filter() {
+        let filter = MaxLengthFilter::new(5);
+
+        assert!(filter.apply("héllo".to_string(), Uuid::nil()).is_ok());
+        assert_eq!(
+            filter.apply("hello!".to_string(), Uuid::nil()),
+            Err("message is longer than 5 characters".to_string())
+        );
+    }
+    #[test]
+    fn test_blocklist_filter() {
+        let words = vec!["Darn".to_string()];
+        let room = Uuid::nil();
+
+        let mask = BlocklistFilter::new(&words, BlocklistAction::Mask);
+        assert_eq!(
+            mask.apply("darn it, DARN!".to_string(), room),
+            Ok("**** it, ****!".to_string())
+        );
+        assert_eq!(
+            mask.apply("darning socks".to_string(), room),
+            Ok("darning socks".to_string())
+        );
+
+        let reject = BlocklistFilter::new(&words, BlocklistAction::Reject);
+        assert!(reject.apply("oh darn".to_string(), room).is_err());
+    }
+    #[test]
+    fn test_link_filter_per_room() {
+        let blocked_room = Uuid::new_v4();
+        let filter = LinkFilter::new(false, HashSet::from([blocked_room]));
+
+        assert!(
+            filter
+                .apply("see HTTPS://example.com".to_string(), blocked_room)
+                .is_err()
+        );
+        assert!(
+            filter
+                .apply("see https://example.com".to_string(), Uuid::new_v4())
+                .is_ok()
+        );
+    }
+    #[test]
+    fn test_pipeline_from_config() {
+        let pipeline = FilterPipeline::from_config(&FilterConfig {
+            max_length: 10,
+            blocklist: vec!["darn".to_string()],
+            ..FilterConfig::default()
+        });
+
+        assert_eq!(
+            pipeline.apply("darn\u{7}".to_string(), Uuid::nil()),
+            Ok("****".to_string())
+        );
+        assert!(pipeline.apply("a".repeat(11), Uuid::nil()).is_err());
+    }
+}
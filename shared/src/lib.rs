@@ -1,3 +1,7 @@
+pub mod config;
+pub mod filters;
 pub mod helpers;
+pub mod metrics;
 pub mod models;
+pub mod spam;
 pub mod types;
@@ -0,0 +1,208 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Default)]
+pub struct Metrics {
+    pub active_sockets: AtomicU64,
+    pub broadcast_lag_events: AtomicU64,
+    pub broadcast_lagged_messages: AtomicU64,
+    pub messages_total: AtomicU64,
+    pub redis_errors: AtomicU64,
+    rate_limit_rejections: Mutex<BTreeMap<String, u64>>,
+    http_latency: Mutex<BTreeMap<(String, String), Histogram>>,
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if value <= bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+impl Metrics {
+    pub fn record_lag(&self, missed: u64) {
+        self.broadcast_lag_events.fetch_add(1, Ordering::Relaxed);
+        self.broadcast_lagged_messages
+            .fetch_add(missed, Ordering::Relaxed);
+    }
+    pub fn record_redis_error(&self) {
+        self.redis_errors.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn record_rate_limited(&self, policy: &str) {
+        let mut rejections = self.rate_limit_rejections.lock().unwrap();
+        *rejections.entry(policy.to_string()).or_default() += 1;
+    }
+    pub fn rate_limit_rejections(&self, policy: &str) -> u64 {
+        let rejections = self.rate_limit_rejections.lock().unwrap();
+        rejections.get(policy).copied().unwrap_or(0)
+    }
+    pub fn record_http_request(&self, method: &str, route: &str, elapsed: Duration) {
+        let mut http_latency = self.http_latency.lock().unwrap();
+        http_latency
+            .entry((method.to_string(), route.to_string()))
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        write_metric(
+            &mut out,
+            "chat_active_sockets",
+            "Open websocket connections",
+            "gauge",
+            self.active_sockets.load(Ordering::Relaxed),
+        );
+        write_metric(
+            &mut out,
+            "chat_messages_total",
+            "Chat messages broadcast to rooms",
+            "counter",
+            self.messages_total.load(Ordering::Relaxed),
+        );
+        write_metric(
+            &mut out,
+            "chat_broadcast_lag_events_total",
+            "Times a websocket receiver fell behind its room channel",
+            "counter",
+            self.broadcast_lag_events.load(Ordering::Relaxed),
+        );
+        write_metric(
+            &mut out,
+            "chat_broadcast_lagged_messages_total",
+            "Messages skipped by lagging websocket receivers",
+            "counter",
+            self.broadcast_lagged_messages.load(Ordering::Relaxed),
+        );
+        write_metric(
+            &mut out,
+            "chat_redis_errors_total",
+            "Failed redis operations",
+            "counter",
+            self.redis_errors.load(Ordering::Relaxed),
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP chat_rate_limit_rejections_total Requests rejected by a rate limit policy"
+        );
+        let _ = writeln!(out, "# TYPE chat_rate_limit_rejections_total counter");
+        for (policy, count) in self.rate_limit_rejections.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "chat_rate_limit_rejections_total{{policy=\"{}\"}} {}",
+                policy, count
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP chat_http_request_duration_seconds HTTP request latency by route"
+        );
+        let _ = writeln!(out, "# TYPE chat_http_request_duration_seconds histogram");
+        for ((method, route), histogram) in self.http_latency.lock().unwrap().iter() {
+            let labels = format!("method=\"{}\",route=\"{}\"", method, route);
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                let _ = writeln!(
+                    out,
+                    "chat_http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, bound, count
+                );
+            }
+            let _ = writeln!(
+                out,
+                "chat_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "chat_http_request_duration_seconds_sum{{{}}} {}",
+                labels, histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "chat_http_request_duration_seconds_count{{{}}} {}",
+                labels, histogram.count
+            );
+        }
+
+        out
+    }
+}
+
+pub fn write_metric(out: &mut String, name: &str, help: &str, kind: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Metrics;
+    use std::{sync::atomic::Ordering, time::Duration};
+
+    #[test]
+    fn test_render_counters() {
+        let metrics = Metrics::default();
+        metrics.active_sockets.fetch_add(2, Ordering::Relaxed);
+        metrics.record_lag(5);
+        metrics.record_rate_limited("chat");
+        metrics.record_rate_limited("chat");
+
+        let out = metrics.render();
+
+        assert!(out.contains("chat_active_sockets 2\n"));
+        assert!(out.contains("chat_broadcast_lag_events_total 1\n"));
+        assert!(out.contains("chat_broadcast_lagged_messages_total 5\n"));
+        assert!(out.contains("chat_rate_limit_rejections_total{policy=\"chat\"} 2\n"));
+    }
+    #[test]
+    fn test_render_http_histogram() {
+        let metrics = Metrics::default();
+        metrics.record_http_request("GET", "/room/list", Duration::from_millis(20));
+        metrics.record_http_request("GET", "/room/list", Duration::from_millis(300));
+
+        let out = metrics.render();
+        let labels = "method=\"GET\",route=\"/room/list\"";
+
+        assert!(out.contains(&format!(
+            "chat_http_request_duration_seconds_bucket{{{},le=\"0.01\"}} 0\n",
+            labels
+        )));
+        assert!(out.contains(&format!(
+            "chat_http_request_duration_seconds_bucket{{{},le=\"0.025\"}} 1\n",
+            labels
+        )));
+        assert!(out.contains(&format!(
+            "chat_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} 2\n",
+            labels
+        )));
+        assert!(out.contains(&format!(
+            "chat_http_request_duration_seconds_count{{{}}} 2\n",
+            labels
+        )));
+    }
+}
@@ -1,22 +1,101 @@
 use std::sync::Arc;
 
+use axum::Json;
+use serde_json::Value;
 use sqlx::PgPool;
+use tokio::sync::{
+    Mutex, MutexGuard,
+    broadcast::{self, Receiver, Sender, error::SendError},
+    watch,
+};
 
-use crate::types::Channel;
+use crate::{
+    config::Config, filters::FilterPipeline, metrics::Metrics, spam::SpamDetector, types::Channel,
+};
 
 #[derive(Clone)]
 pub struct AppState {
     pub db_pool: Arc<PgPool>,
     pub redis_client: Arc<redis::Client>,
     pub channels: Channel,
+    pub config: Arc<Config>,
+    pub metrics: Arc<Metrics>,
+    pub filters: Arc<FilterPipeline>,
+    pub spam: Arc<SpamDetector>,
+    pub shutdown: watch::Sender<bool>,
 }
 
 impl AppState {
-    pub fn new(db_pool: Arc<PgPool>, redis_client: Arc<redis::Client>, channels: Channel) -> Self {
+    pub fn new(
+        db_pool: Arc<PgPool>,
+        redis_client: Arc<redis::Client>,
+        channels: Channel,
+        config: Arc<Config>,
+    ) -> Self {
         Self {
             db_pool,
             redis_client,
             channels,
+            metrics: Arc::new(Metrics::default()),
+            filters: Arc::new(FilterPipeline::from_config(&config.filter)),
+            spam: Arc::new(SpamDetector::new(config.spam.clone())),
+            config,
+            shutdown: watch::Sender::new(false),
         }
     }
 }
+
+pub struct RoomChannel {
+    pub tx: RoomSender,
+    pub members: Vec<String>,
+}
+
+impl RoomChannel {
+    pub fn new(capacity: usize, last_seq: i64) -> Self {
+        Self {
+            tx: RoomSender::new(capacity, last_seq),
+            members: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RoomSender {
+    tx: Sender<Json<Value>>,
+    seq: Arc<Mutex<i64>>,
+}
+
+impl RoomSender {
+    pub fn new(capacity: usize, last_seq: i64) -> Self {
+        Self {
+            tx: broadcast::channel(capacity).0,
+            seq: Arc::new(Mutex::new(last_seq)),
+        }
+    }
+    // the last broadcast seq; hold it from storing a message until it's broadcast,
+    // receivers drop anything at or below the last seq they saw
+    pub async fn lock_seq(&self) -> MutexGuard<'_, i64> {
+        self.seq.lock().await
+    }
+    // for events that aren't stored; only stored messages take a seq, so a rebuilt
+    // channel continues from the latest stored one without reusing seqs
+    pub fn send(&self, frame: Value) -> Result<usize, SendError<Json<Value>>> {
+        self.tx.send(Json(frame))
+    }
+    pub fn send_with_seq(
+        &self,
+        mut frame: Value,
+        seq: i64,
+    ) -> Result<usize, SendError<Json<Value>>> {
+        if let Value::Object(map) = &mut frame {
+            map.insert("seq".to_string(), seq.into());
+        }
+        self.tx.send(Json(frame))
+    }
+    pub fn subscribe(&self) -> Receiver<Json<Value>> {
+        self.tx.subscribe()
+    }
+    pub fn receiver_count(&self) -> usize {
+        self.tx.receiver_count()
+    }
+}
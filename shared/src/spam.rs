@@ -0,0 +1,277 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use uuid::Uuid;
+
+use crate::config::SpamConfig;
+
+// only the start of long messages is compared, keeps the edit distance cheap
+const COMPARE_CHARS: usize = 200;
+const SWEEP_EVERY: u64 = 1024;
+
+struct Activity {
+    first_seen: Instant,
+    recent: VecDeque<(Instant, Uuid, String)>,
+}
+
+// per-process view of what each sender said recently; a restart forgets it
+pub struct SpamDetector {
+    config: SpamConfig,
+    users: Mutex<HashMap<String, Activity>>,
+    checks: AtomicU64,
+}
+
+impl SpamDetector {
+    pub fn new(config: SpamConfig) -> Self {
+        Self {
+            config,
+            users: Mutex::new(HashMap::new()),
+            checks: AtomicU64::new(0),
+        }
+    }
+    // returns the reason when the message looks like spam
+    pub fn check(&self, username: &str, room_uuid: Uuid, message: &str) -> Option<String> {
+        self.check_at(username, room_uuid, message, Instant::now())
+    }
+    fn check_at(
+        &self,
+        username: &str,
+        room_uuid: Uuid,
+        message: &str,
+        now: Instant,
+    ) -> Option<String> {
+        if !self.config.enabled {
+            return None;
+        }
+        self.maybe_sweep(now);
+
+        let duplicate_window = Duration::from_secs(self.config.duplicate_window_secs);
+        let burst_window = Duration::from_secs(self.config.burst_window_secs);
+        let keep = duplicate_window.max(burst_window);
+        let normalized = normalize(message);
+
+        let mut users = self.users.lock().unwrap();
+        let activity = users.entry(username.to_string()).or_insert(Activity {
+            first_seen: now,
+            recent: VecDeque::new(),
+        });
+        while let Some((at, _, _)) = activity.recent.front() {
+            if now.duration_since(*at) <= keep {
+                break;
+            }
+            activity.recent.pop_front();
+        }
+
+        let duplicates = activity
+            .recent
+            .iter()
+            .filter(|(at, _, previous)| {
+                now.duration_since(*at) <= duplicate_window
+                    && similarity(previous, &normalized) >= self.config.similarity_threshold
+            })
+            .count();
+        let in_burst: Vec<Uuid> = activity
+            .recent
+            .iter()
+            .filter(|(at, _, _)| now.duration_since(*at) <= burst_window)
+            .map(|(_, room, _)| *room)
+            .collect();
+        let in_room = in_burst.iter().filter(|room| **room == room_uuid).count();
+        let is_new = now.duration_since(activity.first_seen)
+            < Duration::from_secs(self.config.new_account_secs);
+
+        activity.recent.push_back((now, room_uuid, normalized));
+
+        if duplicates >= self.config.max_duplicates {
+            return Some("repeated message".to_string());
+        }
+        if is_new && in_burst.len() >= self.config.new_account_burst_limit {
+            return Some("too many messages from a new session".to_string());
+        }
+        if in_room >= self.config.room_burst_limit {
+            return Some("too many messages in this room".to_string());
+        }
+        if in_burst.len() >= self.config.global_burst_limit {
+            return Some("too many messages across rooms".to_string());
+        }
+
+        None
+    }
+    // drops senders who went quiet and are past the new-session window
+    fn maybe_sweep(&self, now: Instant) {
+        if !(self.checks.fetch_add(1, Ordering::Relaxed) + 1).is_multiple_of(SWEEP_EVERY) {
+            return;
+        }
+
+        let keep = Duration::from_secs(
+            self.config
+                .duplicate_window_secs
+                .max(self.config.burst_window_secs)
+                .max(self.config.new_account_secs),
+        );
+        self.users.lock().unwrap().retain(|_, activity| {
+            let last = activity
+                .recent
+                .back()
+                .map(|(at, _, _)| *at)
+                .unwrap_or(activity.first_seen);
+            now.duration_since(last) <= keep
+        });
+    }
+}
+
+// lowercase alphanumeric words joined by single spaces
+fn normalize(message: &str) -> String {
+    message
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(COMPARE_CHARS)
+        .collect()
+}
+
+// 1 - levenshtein distance / longer length
+fn similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    1.0 - previous[b.len()] as f64 / longest as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use uuid::Uuid;
+
+    use super::{SpamDetector, similarity};
+    use crate::config::SpamConfig;
+
+    fn detector() -> SpamDetector {
+        SpamDetector::new(SpamConfig {
+            new_account_secs: 0,
+            ..SpamConfig::default()
+        })
+    }
+
+    #[test]
+    fn test_similarity() {
+        assert_eq!(similarity("buy now", "buy now"), 1.0);
+        assert!(similarity("buy cheap pills now", "buy cheap pills now!!") > 0.9);
+        assert!(similarity("hello there", "see you tomorrow") < 0.5);
+    }
+    #[test]
+    fn test_repeated_messages() {
+        let detector = detector();
+        let room = Uuid::new_v4();
+        let start = Instant::now();
+
+        for i in 0..3 {
+            let at = start + Duration::from_secs(i * 5);
+            assert_eq!(
+                detector.check_at("alice", room, "Buy cheap pills", at),
+                None
+            );
+        }
+        let at = start + Duration::from_secs(15);
+        assert_eq!(
+            detector.check_at("alice", room, "buy  CHEAP pills!", at),
+            Some("repeated message".to_string())
+        );
+        // other senders are tracked separately
+        assert_eq!(detector.check_at("bob", room, "buy cheap pills", at), None);
+        // outside the window the repeats are forgotten
+        let later = start + Duration::from_secs(200);
+        assert_eq!(
+            detector.check_at("alice", room, "buy cheap pills", later),
+            None
+        );
+    }
+    #[test]
+    fn test_bursts() {
+        let detector = detector();
+        let room = Uuid::new_v4();
+        let now = Instant::now();
+
+        for i in 0..8 {
+            assert_eq!(
+                detector.check_at("alice", room, &format!("msg {i}"), now),
+                None
+            );
+        }
+        assert_eq!(
+            detector.check_at("alice", room, "one more", now),
+            Some("too many messages in this room".to_string())
+        );
+
+        for i in 0..15 {
+            let result = detector.check_at("bob", Uuid::new_v4(), &format!("msg {i}"), now);
+            assert_eq!(result, None);
+        }
+        assert_eq!(
+            detector.check_at("bob", Uuid::new_v4(), "one more", now),
+            Some("too many messages across rooms".to_string())
+        );
+    }
+    #[test]
+    fn test_new_session_throttle() {
+        let detector = SpamDetector::new(SpamConfig::default());
+        let room = Uuid::new_v4();
+        let start = Instant::now();
+
+        for i in 0..3 {
+            assert_eq!(
+                detector.check_at("alice", room, &format!("msg {i}"), start),
+                None
+            );
+        }
+        assert_eq!(
+            detector.check_at("alice", room, "one more", start),
+            Some("too many messages from a new session".to_string())
+        );
+
+        // once the session is older the normal burst limit applies
+        let later = start + Duration::from_secs(61);
+        for i in 0..8 {
+            assert_eq!(
+                detector.check_at("alice", room, &format!("later {i}"), later),
+                None
+            );
+        }
+    }
+    #[test]
+    fn test_disabled() {
+        let detector = SpamDetector::new(SpamConfig {
+            enabled: false,
+            ..SpamConfig::default()
+        });
+        let now = Instant::now();
+        for _ in 0..20 {
+            assert_eq!(detector.check_at("alice", Uuid::nil(), "same", now), None);
+        }
+    }
+}
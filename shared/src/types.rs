@@ -1,9 +1,9 @@
 use std::{collections::HashMap, sync::Arc};
 
-use axum::Json;
-use serde_json::Value;
-use tokio::sync::{Mutex, broadcast::Sender};
+use tokio::sync::Mutex;
 use uuid::Uuid;
 
+use crate::models::RoomChannel;
+
 pub type DefaultError = Box<dyn std::error::Error>;
-pub type Channel = Arc<Mutex<HashMap<Uuid, Sender<Json<Value>>>>>;
+pub type Channel = Arc<Mutex<HashMap<Uuid, RoomChannel>>>;